@@ -0,0 +1,113 @@
+// Compares run_program, which runs the bytecode, with running the same programs one
+// instruction at a time. Run it with `cargo bench`, or `cargo bench --features jit` to
+// measure run_jit too.
+extern crate whitespace;
+
+use std::time::{Duration, Instant};
+use whitespace::assembler::assemble;
+use whitespace::instruction::Instruction;
+use whitespace::parser::{parse_program_with, ParseOptions};
+use whitespace::vm::VirtualMachine;
+
+// Counts to a million, to measure the dispatch more than the I/O
+const HOT_LOOP: &str = "
+        push 0
+loop:   push 1
+        add
+        dup
+        push 1000000
+        sub
+        jn loop
+        end
+";
+
+fn step_through(instructions: &[Instruction], input: &str) -> Vec<u8> {
+    let mut vm = VirtualMachine::with_io(instructions, input.as_bytes(), Vec::new());
+    while vm.instructions().get(vm.ip) != Some(&Instruction::EndOfProgram) {
+        if vm.run_instruction().is_err() {
+            break;
+        }
+    }
+    vm.output
+}
+
+fn run_program(instructions: &[Instruction], input: &str) -> Vec<u8> {
+    let mut vm = VirtualMachine::with_io(instructions, input.as_bytes(), Vec::new());
+    let _ = vm.run_program();
+    vm.output
+}
+
+#[cfg(feature = "jit")]
+fn run_jit(instructions: &[Instruction], input: &str) -> Vec<u8> {
+    let mut vm = VirtualMachine::with_io(instructions, input.as_bytes(), Vec::new());
+    let _ = vm.run_jit();
+    vm.output
+}
+
+// The JIT column, empty without the jit feature
+#[cfg(feature = "jit")]
+fn measure_jit(instructions: &[Instruction], input: &str) -> String {
+    assert_eq!(
+        run_program(instructions, input),
+        run_jit(instructions, input)
+    );
+    format!("{:?}", measure(|| run_jit(instructions, input)))
+}
+
+#[cfg(not(feature = "jit"))]
+fn measure_jit(_: &[Instruction], _: &str) -> String {
+    "-".to_string()
+}
+
+// The average duration of a run, over enough runs to last about half a second
+fn measure(run: impl Fn() -> Vec<u8>) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs < 3 || start.elapsed() < Duration::from_millis(500) {
+        std::hint::black_box(run());
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    let options = ParseOptions {
+        allow_sign_only_numbers: true,
+        ..ParseOptions::default()
+    };
+    let mut programs = vec![("hot loop".to_string(), assemble(HOT_LOOP).unwrap(), "")];
+    let examples = [
+        ("count2", ""),
+        ("hworld2", ""),
+        ("fact", "12\n"),
+        ("hanoi", "10\n"),
+        ("loctest", "3\n4\n"),
+        ("calc", "5\n3\n-1\n"),
+    ];
+    for (name, input) in examples.iter() {
+        let source = std::fs::read_to_string(format!("examples/{}.ws", name)).unwrap();
+        let instructions = parse_program_with(&source, &options).unwrap();
+        programs.push((name.to_string(), instructions, input));
+    }
+
+    println!(
+        "{:<10} {:>15} {:>15} {:>8} {:>15}",
+        "program", "instructions", "bytecode", "speedup", "jit"
+    );
+    for (name, instructions, input) in programs.iter() {
+        assert_eq!(
+            step_through(instructions, input),
+            run_program(instructions, input)
+        );
+        let slow = measure(|| step_through(instructions, input));
+        let fast = measure(|| run_program(instructions, input));
+        println!(
+            "{:<10} {:>15?} {:>15?} {:>7.2}x {:>15}",
+            name,
+            slow,
+            fast,
+            slow.as_secs_f64() / fast.as_secs_f64(),
+            measure_jit(instructions, input)
+        );
+    }
+}
@@ -0,0 +1,398 @@
+use crate::bignum::BigNum;
+use std::fmt;
+use std::str::FromStr;
+
+/// What happens when the result of an operation on integers does not fit in an `i32`.
+/// Running the virtual machine with `BigNum` values avoids overflows altogether.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum IntegerMode {
+    /// The operation fails with an overflow error
+    #[default]
+    Checked,
+    /// The result wraps around, like two's complement hardware
+    Wrapping,
+    /// The result is clamped to `i32::MIN` or `i32::MAX`
+    Saturating,
+    /// The result becomes a `BigNum`, the values being `Promoted` ones. An `i32` value can
+    /// not grow, so every operation on `i32` values fails with `CanNotPromote` instead.
+    Promote,
+}
+
+// checked, wrapping, saturating or promote
+impl FromStr for IntegerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<IntegerMode, String> {
+        match s {
+            "checked" => Ok(IntegerMode::Checked),
+            "wrapping" => Ok(IntegerMode::Wrapping),
+            "saturating" => Ok(IntegerMode::Saturating),
+            "promote" => Ok(IntegerMode::Promote),
+            _ => Err(format!("unknown integer mode {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticError {
+    DivisionByZero,
+    Overflow,
+    /// The `Promote` mode was used with values that can not grow, like `i32` ones
+    CanNotPromote,
+}
+
+pub fn add(l: i32, r: i32, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+    match mode {
+        IntegerMode::Checked => l.checked_add(r).ok_or(ArithmeticError::Overflow),
+        IntegerMode::Wrapping => Ok(l.wrapping_add(r)),
+        IntegerMode::Saturating => Ok(l.saturating_add(r)),
+        IntegerMode::Promote => Err(ArithmeticError::CanNotPromote),
+    }
+}
+
+pub fn sub(l: i32, r: i32, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+    match mode {
+        IntegerMode::Checked => l.checked_sub(r).ok_or(ArithmeticError::Overflow),
+        IntegerMode::Wrapping => Ok(l.wrapping_sub(r)),
+        IntegerMode::Saturating => Ok(l.saturating_sub(r)),
+        IntegerMode::Promote => Err(ArithmeticError::CanNotPromote),
+    }
+}
+
+pub fn mul(l: i32, r: i32, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+    match mode {
+        IntegerMode::Checked => l.checked_mul(r).ok_or(ArithmeticError::Overflow),
+        IntegerMode::Wrapping => Ok(l.wrapping_mul(r)),
+        IntegerMode::Saturating => Ok(l.saturating_mul(r)),
+        IntegerMode::Promote => Err(ArithmeticError::CanNotPromote),
+    }
+}
+
+// Division rounding towards negative infinity, like Haskell's `div`
+pub fn div(l: i32, r: i32, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+    if mode == IntegerMode::Promote {
+        return Err(ArithmeticError::CanNotPromote);
+    }
+    if r == 0 {
+        return Err(ArithmeticError::DivisionByZero);
+    }
+    // i32::MIN / -1 is the only division that overflows
+    let quotient = match (l.checked_div(r), mode) {
+        (Some(q), _) => q,
+        (None, IntegerMode::Wrapping) => return Ok(i32::MIN),
+        (None, IntegerMode::Saturating) => return Ok(i32::MAX),
+        (None, _) => return Err(ArithmeticError::Overflow),
+    };
+    if l % r != 0 && (l < 0) != (r < 0) {
+        Ok(quotient - 1)
+    } else {
+        Ok(quotient)
+    }
+}
+
+// Modulo whose result has the sign of the divisor, like Haskell's `mod`. It never overflows.
+pub fn modulo(l: i32, r: i32) -> Result<i32, ArithmeticError> {
+    if r == 0 {
+        return Err(ArithmeticError::DivisionByZero);
+    }
+    let remainder = l.wrapping_rem(r);
+    if remainder != 0 && (remainder < 0) != (r < 0) {
+        Ok(remainder + r)
+    } else {
+        Ok(remainder)
+    }
+}
+
+/// The values held by the stack and the heap of the virtual machine
+pub trait Number: Clone + PartialEq + fmt::Debug + fmt::Display {
+    fn from_i32(value: i32) -> Self;
+    /// Converts a literal, applying `mode` when it does not fit
+    fn from_bignum(value: &BigNum, mode: IntegerMode) -> Result<Self, ArithmeticError>;
+    fn add(&self, r: &Self, mode: IntegerMode) -> Result<Self, ArithmeticError>;
+    fn sub(&self, r: &Self, mode: IntegerMode) -> Result<Self, ArithmeticError>;
+    fn mul(&self, r: &Self, mode: IntegerMode) -> Result<Self, ArithmeticError>;
+    fn div(&self, r: &Self, mode: IntegerMode) -> Result<Self, ArithmeticError>;
+    fn modulo(&self, r: &Self) -> Result<Self, ArithmeticError>;
+    fn is_zero(&self) -> bool;
+    fn is_negative(&self) -> bool;
+    /// None when the value does not fit in an `i64`
+    fn to_i64(&self) -> Option<i64>;
+    /// Whether the values can be used in the `Promote` mode
+    fn can_promote() -> bool;
+}
+
+impl Number for i32 {
+    fn from_i32(value: i32) -> i32 {
+        value
+    }
+    fn from_bignum(value: &BigNum, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+        match (value.to_i32(), mode) {
+            (_, IntegerMode::Promote) => Err(ArithmeticError::CanNotPromote),
+            (Some(v), _) => Ok(v),
+            (None, IntegerMode::Checked) => Err(ArithmeticError::Overflow),
+            (None, IntegerMode::Wrapping) => Ok(value.wrapping_to_i32()),
+            (None, IntegerMode::Saturating) if value.is_negative() => Ok(i32::MIN),
+            (None, IntegerMode::Saturating) => Ok(i32::MAX),
+        }
+    }
+    fn add(&self, r: &i32, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+        add(*self, *r, mode)
+    }
+    fn sub(&self, r: &i32, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+        sub(*self, *r, mode)
+    }
+    fn mul(&self, r: &i32, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+        mul(*self, *r, mode)
+    }
+    fn div(&self, r: &i32, mode: IntegerMode) -> Result<i32, ArithmeticError> {
+        div(*self, *r, mode)
+    }
+    fn modulo(&self, r: &i32) -> Result<i32, ArithmeticError> {
+        modulo(*self, *r)
+    }
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+    fn is_negative(&self) -> bool {
+        *self < 0
+    }
+    fn to_i64(&self) -> Option<i64> {
+        Some(i64::from(*self))
+    }
+    fn can_promote() -> bool {
+        false
+    }
+}
+
+// BigNum never overflows, so the integer mode does not matter
+impl Number for BigNum {
+    fn from_i32(value: i32) -> BigNum {
+        BigNum::from(value)
+    }
+    fn from_bignum(value: &BigNum, _: IntegerMode) -> Result<BigNum, ArithmeticError> {
+        Ok(value.clone())
+    }
+    fn add(&self, r: &BigNum, _: IntegerMode) -> Result<BigNum, ArithmeticError> {
+        Ok(BigNum::add(self, r))
+    }
+    fn sub(&self, r: &BigNum, _: IntegerMode) -> Result<BigNum, ArithmeticError> {
+        Ok(BigNum::sub(self, r))
+    }
+    fn mul(&self, r: &BigNum, _: IntegerMode) -> Result<BigNum, ArithmeticError> {
+        Ok(BigNum::mul(self, r))
+    }
+    fn div(&self, r: &BigNum, _: IntegerMode) -> Result<BigNum, ArithmeticError> {
+        BigNum::div(self, r).ok_or(ArithmeticError::DivisionByZero)
+    }
+    fn modulo(&self, r: &BigNum) -> Result<BigNum, ArithmeticError> {
+        BigNum::modulo(self, r).ok_or(ArithmeticError::DivisionByZero)
+    }
+    fn is_zero(&self) -> bool {
+        BigNum::is_zero(self)
+    }
+    fn is_negative(&self) -> bool {
+        BigNum::is_negative(self)
+    }
+    fn to_i64(&self) -> Option<i64> {
+        BigNum::to_i64(self)
+    }
+    fn can_promote() -> bool {
+        true
+    }
+}
+
+/// The values of `IntegerMode::Promote`: an `i32` until a result does not fit, a `BigNum`
+/// after that. A result that fits again is an `i32`, so that equal values are equal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Promoted {
+    Small(i32),
+    Big(BigNum),
+}
+
+impl Promoted {
+    fn new(value: BigNum) -> Promoted {
+        match value.to_i32() {
+            Some(v) => Promoted::Small(v),
+            None => Promoted::Big(value),
+        }
+    }
+
+    fn to_bignum(&self) -> BigNum {
+        match self {
+            Promoted::Small(v) => BigNum::from(*v),
+            Promoted::Big(v) => v.clone(),
+        }
+    }
+
+    // Computes with i32 when both values are small, with BigNum when they are not or when
+    // the result overflows
+    fn promote(
+        &self,
+        r: &Promoted,
+        small: fn(i32, i32, IntegerMode) -> Result<i32, ArithmeticError>,
+        big: fn(&BigNum, &BigNum, IntegerMode) -> Result<BigNum, ArithmeticError>,
+    ) -> Result<Promoted, ArithmeticError> {
+        if let (Promoted::Small(l), Promoted::Small(r)) = (self, r) {
+            match small(*l, *r, IntegerMode::Checked) {
+                Err(ArithmeticError::Overflow) => {}
+                result => return result.map(Promoted::Small),
+            }
+        }
+        big(&self.to_bignum(), &r.to_bignum(), IntegerMode::Checked).map(Promoted::new)
+    }
+}
+
+impl fmt::Display for Promoted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Promoted::Small(v) => write!(f, "{}", v),
+            Promoted::Big(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+// Promoted values never overflow, so the integer mode does not matter either
+impl Number for Promoted {
+    fn from_i32(value: i32) -> Promoted {
+        Promoted::Small(value)
+    }
+    fn from_bignum(value: &BigNum, _: IntegerMode) -> Result<Promoted, ArithmeticError> {
+        Ok(Promoted::new(value.clone()))
+    }
+    fn add(&self, r: &Promoted, _: IntegerMode) -> Result<Promoted, ArithmeticError> {
+        self.promote(r, add, <BigNum as Number>::add)
+    }
+    fn sub(&self, r: &Promoted, _: IntegerMode) -> Result<Promoted, ArithmeticError> {
+        self.promote(r, sub, <BigNum as Number>::sub)
+    }
+    fn mul(&self, r: &Promoted, _: IntegerMode) -> Result<Promoted, ArithmeticError> {
+        self.promote(r, mul, <BigNum as Number>::mul)
+    }
+    fn div(&self, r: &Promoted, _: IntegerMode) -> Result<Promoted, ArithmeticError> {
+        self.promote(r, div, <BigNum as Number>::div)
+    }
+    fn modulo(&self, r: &Promoted) -> Result<Promoted, ArithmeticError> {
+        match (self, r) {
+            (Promoted::Small(l), Promoted::Small(r)) => modulo(*l, *r).map(Promoted::Small),
+            _ => Number::modulo(&self.to_bignum(), &r.to_bignum()).map(Promoted::new),
+        }
+    }
+    fn is_zero(&self) -> bool {
+        *self == Promoted::Small(0)
+    }
+    fn is_negative(&self) -> bool {
+        match self {
+            Promoted::Small(v) => *v < 0,
+            Promoted::Big(v) => v.is_negative(),
+        }
+    }
+    fn to_i64(&self) -> Option<i64> {
+        match self {
+            Promoted::Small(v) => Some(i64::from(*v)),
+            Promoted::Big(v) => v.to_i64(),
+        }
+    }
+    fn can_promote() -> bool {
+        true
+    }
+}
+
+#[test]
+fn overflow_modes_work() {
+    assert_eq!(
+        Err(ArithmeticError::Overflow),
+        add(i32::MAX, 1, IntegerMode::Checked)
+    );
+    assert_eq!(Ok(i32::MIN), add(i32::MAX, 1, IntegerMode::Wrapping));
+    assert_eq!(Ok(i32::MAX), add(i32::MAX, 1, IntegerMode::Saturating));
+
+    assert_eq!(
+        Err(ArithmeticError::Overflow),
+        sub(i32::MIN, 1, IntegerMode::Checked)
+    );
+    assert_eq!(Ok(i32::MAX), sub(i32::MIN, 1, IntegerMode::Wrapping));
+    assert_eq!(Ok(i32::MIN), sub(i32::MIN, 1, IntegerMode::Saturating));
+
+    assert_eq!(
+        Err(ArithmeticError::Overflow),
+        mul(1 << 16, 1 << 16, IntegerMode::Checked)
+    );
+    assert_eq!(Ok(0), mul(1 << 16, 1 << 16, IntegerMode::Wrapping));
+    assert_eq!(
+        Ok(i32::MIN),
+        mul(-(1 << 16), 1 << 16, IntegerMode::Saturating)
+    );
+
+    assert_eq!(
+        Err(ArithmeticError::Overflow),
+        div(i32::MIN, -1, IntegerMode::Checked)
+    );
+    assert_eq!(Ok(i32::MIN), div(i32::MIN, -1, IntegerMode::Wrapping));
+    assert_eq!(Ok(i32::MAX), div(i32::MIN, -1, IntegerMode::Saturating));
+    assert_eq!(Ok(0), modulo(i32::MIN, -1));
+}
+
+#[test]
+fn literals_follow_the_integer_mode() {
+    let big = BigNum::from(1i64 << 32).add(&BigNum::from(5));
+    assert_eq!(
+        Err(ArithmeticError::Overflow),
+        i32::from_bignum(&big, IntegerMode::Checked)
+    );
+    assert_eq!(Ok(5), i32::from_bignum(&big, IntegerMode::Wrapping));
+    assert_eq!(
+        Ok(i32::MAX),
+        i32::from_bignum(&big, IntegerMode::Saturating)
+    );
+    assert_eq!(
+        Ok(i32::MIN),
+        i32::from_bignum(&big.neg(), IntegerMode::Saturating)
+    );
+    assert_eq!(
+        Ok(big.clone()),
+        BigNum::from_bignum(&big, IntegerMode::Checked)
+    );
+}
+
+#[test]
+fn promoted_values_grow_and_shrink() {
+    let max = Promoted::Small(i32::MAX);
+    let one = Promoted::Small(1);
+    let big = max.add(&one, IntegerMode::Promote).unwrap();
+    assert_eq!(Promoted::Big(BigNum::from(1i64 << 31)), big);
+    assert_eq!(Ok(max.clone()), big.sub(&one, IntegerMode::Promote));
+    assert_eq!(
+        "4611686014132420609",
+        max.mul(&max, IntegerMode::Promote).unwrap().to_string()
+    );
+    let min = Promoted::Small(i32::MIN);
+    assert_eq!(
+        Ok(Promoted::Big(BigNum::from(1i64 << 31))),
+        min.div(&Promoted::Small(-1), IntegerMode::Promote)
+    );
+    assert_eq!(
+        Err(ArithmeticError::DivisionByZero),
+        big.div(&Promoted::Small(0), IntegerMode::Promote)
+    );
+    assert_eq!(Ok(Promoted::Small(-1)), big.modulo(&Promoted::Small(-3)));
+
+    // i32 values can not be promoted, whether the result fits or not
+    assert_eq!(
+        Err(ArithmeticError::CanNotPromote),
+        add(i32::MAX, 1, IntegerMode::Promote)
+    );
+    assert_eq!(
+        Err(ArithmeticError::CanNotPromote),
+        div(4, 2, IntegerMode::Promote)
+    );
+    let literal = BigNum::from(1i64 << 32);
+    assert_eq!(
+        Err(ArithmeticError::CanNotPromote),
+        i32::from_bignum(&literal, IntegerMode::Promote)
+    );
+    assert!(!i32::can_promote() && BigNum::can_promote() && Promoted::can_promote());
+    assert_eq!(
+        Ok(Promoted::Big(literal.clone())),
+        Promoted::from_bignum(&literal, IntegerMode::Checked)
+    );
+}
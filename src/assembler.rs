@@ -0,0 +1,523 @@
+//! A readable assembly language for whitespace programs. There is one instruction per
+//! line, optionally preceded by a label definition such as `loop:`, and comments start with
+//! `;`:
+//!
+//! ```text
+//! ; prints the numbers from 1 to 10
+//!         push 1
+//! loop:   dup
+//!         printi
+//!         push '\n'
+//!         printc
+//!         push 1
+//!         add
+//!         dup
+//!         push 11
+//!         sub
+//!         jz end
+//!         jmp loop
+//! end:    drop
+//!         end
+//! ```
+//!
+//! Numbers are decimal (`-42`), hexadecimal (`0x2a`) or characters (`'*'`, `'\n'`).
+//! Labels are names made of letters, digits, `_`, `.` and `$`, or any string between double
+//! quotes, like `call "print string"`. `label name` is the same as `name:`. A name made of
+//! `_` and two lowercase hexadecimal digits per character, like `_0a05`, is a generated name
+//! for a label that can not be printed: it stands for the characters 0x0a and 0x05.
+
+use crate::bignum::BigNum;
+use crate::encoder::{encode_program_with, EncodeOptions};
+use crate::instruction::{label_from_bits, Instruction};
+use crate::parser::Form;
+use std::error::Error;
+use std::fmt;
+
+// Every mnemonic, and what follows it
+pub(crate) const MNEMONICS: &[(&str, Form)] = &[
+    // stack
+    ("push", Form::Push),
+    ("dup", Form::Simple(Instruction::Duplicate)),
+    ("copy", Form::Number(Instruction::CopyNth)),
+    ("swap", Form::Simple(Instruction::Swap)),
+    ("drop", Form::Simple(Instruction::Discard)),
+    ("slide", Form::Number(Instruction::Slide)),
+    // arithmetic
+    ("add", Form::Simple(Instruction::Add)),
+    ("sub", Form::Simple(Instruction::Sub)),
+    ("mul", Form::Simple(Instruction::Mul)),
+    ("div", Form::Simple(Instruction::Div)),
+    ("mod", Form::Simple(Instruction::Mod)),
+    // heap
+    ("store", Form::Simple(Instruction::Store)),
+    ("retrieve", Form::Simple(Instruction::Retrieve)),
+    // flow control
+    ("label", Form::Label(Instruction::SetLabel)),
+    ("call", Form::Label(Instruction::CallSubroutine)),
+    ("jmp", Form::Label(Instruction::Jump)),
+    ("jz", Form::Label(Instruction::JZero)),
+    ("jn", Form::Label(Instruction::JNeg)),
+    ("ret", Form::Simple(Instruction::EndOfSubroutine)),
+    ("end", Form::Simple(Instruction::EndOfProgram)),
+    // I/O
+    ("printc", Form::Simple(Instruction::PrintChar)),
+    ("printi", Form::Simple(Instruction::PrintInt)),
+    ("readc", Form::Simple(Instruction::ReadChar)),
+    ("readi", Form::Simple(Instruction::ReadInt)),
+];
+
+/// Why an assembly program could not be assembled, and where
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleError {
+    /// Starts at 1
+    pub line: usize,
+    /// Starts at 1, counted in characters
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl Error for AssembleError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(BigNum),
+    // a label name between double quotes
+    Quoted(String),
+    Colon,
+}
+
+#[derive(Debug)]
+struct Lexeme {
+    token: Token,
+    // the columns of the first character and of the one after the lexeme
+    column: usize,
+    end: usize,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, AssembleError> {
+    let mut instructions = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |column: usize, message: String| AssembleError {
+            line: line_number,
+            column,
+            message,
+        };
+
+        let lexemes = lex(line).map_err(|(column, message)| error(column, message))?;
+        let mut rest = &lexemes[..];
+        if rest.len() >= 2 && rest[1].token == Token::Colon {
+            let name = label_name(&rest[0]).map_err(|message| error(rest[0].column, message))?;
+            instructions.push(Instruction::SetLabel(name));
+            rest = &rest[2..];
+        }
+
+        let (mnemonic, arguments) = match rest.split_first() {
+            Some(split) => split,
+            None => continue,
+        };
+        let name = match &mnemonic.token {
+            Token::Word(word) => word.to_lowercase(),
+            _ => {
+                return Err(error(
+                    mnemonic.column,
+                    "expected an instruction".to_string(),
+                ))
+            }
+        };
+        let form = match MNEMONICS.iter().find(|(m, _)| *m == name) {
+            Some((_, form)) => form,
+            None => {
+                return Err(error(
+                    mnemonic.column,
+                    format!("{} is not an instruction", name),
+                ))
+            }
+        };
+
+        let expects_argument = !matches!(form, Form::Simple(_));
+        if let Some(extra) = arguments.get(if expects_argument { 1 } else { 0 }) {
+            return Err(error(
+                extra.column,
+                format!(
+                    "{} takes {} argument",
+                    name,
+                    if expects_argument { "one" } else { "no" }
+                ),
+            ));
+        }
+        let argument = match arguments.first() {
+            Some(argument) => argument,
+            None if expects_argument => {
+                return Err(error(mnemonic.end, format!("{} expects an argument", name)))
+            }
+            None => mnemonic,
+        };
+
+        let instruction = match form {
+            Form::Simple(instruction) => instruction.clone(),
+            Form::Push => {
+                let value = number(argument).map_err(|message| error(argument.column, message))?;
+                match value.to_i32() {
+                    Some(value) => Instruction::Push(value),
+                    None => Instruction::PushBig(value),
+                }
+            }
+            Form::Number(create) => {
+                let value = number(argument).map_err(|message| error(argument.column, message))?;
+                match value.to_i32() {
+                    Some(value) => create(value),
+                    None => {
+                        return Err(error(
+                            argument.column,
+                            format!("{} does not fit in 32 bits", value),
+                        ))
+                    }
+                }
+            }
+            Form::Label(create) => {
+                let label =
+                    label_name(argument).map_err(|message| error(argument.column, message))?;
+                create(label)
+            }
+        };
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+/// Assembles a program into whitespace source
+pub fn assemble_to_ws(source: &str, options: &EncodeOptions) -> Result<String, AssembleError> {
+    let instructions = assemble(source)?;
+    Ok(encode_program_with(&instructions, options))
+}
+
+fn number(lexeme: &Lexeme) -> Result<BigNum, String> {
+    match &lexeme.token {
+        Token::Number(value) => Ok(value.clone()),
+        _ => Err("expected a number".to_string()),
+    }
+}
+
+fn label_name(lexeme: &Lexeme) -> Result<String, String> {
+    let name = match &lexeme.token {
+        Token::Word(name) => match generated_label(name) {
+            Some(label) => return Ok(label),
+            None => name,
+        },
+        Token::Quoted(name) => name,
+        _ => return Err("expected a label name".to_string()),
+    };
+    if name.is_empty() {
+        return Err("a label name can not be empty".to_string());
+    }
+    // whitespace labels are made of bytes
+    if let Some(c) = name.chars().find(|c| *c > '\u{ff}') {
+        return Err(format!("{} can not be written in a label", c));
+    }
+    Ok(name.clone())
+}
+
+/// The label written as `name` if it is a generated name: `_` followed by two lowercase
+/// hexadecimal digits per byte of the label, then `.` and the last 1 to 7 bits of labels
+/// that are not whole bytes
+pub fn generated_label(name: &str) -> Option<String> {
+    let name = name.strip_prefix('_')?;
+    let (digits, tail) = match name.find('.') {
+        Some(dot) => (&name[..dot], Some(&name[dot + 1..])),
+        None => (name, None),
+    };
+    let valid_tail = match tail {
+        Some(tail) => (1..=7).contains(&tail.len()) && tail.chars().all(|c| c == '0' || c == '1'),
+        None => !digits.is_empty(),
+    };
+    if !valid_tail
+        || digits.len() % 2 != 0
+        || !digits.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+    {
+        return None;
+    }
+    let tail = tail.unwrap_or("");
+    let mut bits = Vec::with_capacity(digits.len() * 4 + tail.len());
+    for i in (0..digits.len()).step_by(2) {
+        let byte = u8::from_str_radix(&digits[i..i + 2], 16).ok()?;
+        bits.extend((0..8).rev().map(|shift| byte >> shift & 1 == 1));
+    }
+    bits.extend(tail.chars().map(|c| c == '1'));
+    Some(label_from_bits(&bits))
+}
+
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+// The lexemes of a line, or the column and the reason of an error
+fn lex(line: &str) -> Result<Vec<Lexeme>, (usize, String)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut lexemes = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let token = if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c == ':' {
+            i += 1;
+            Token::Colon
+        } else if c == '\'' {
+            let (value, next) = quoted(&chars, i, '\'')?;
+            let mut value = value.chars();
+            match (value.next(), value.next()) {
+                (Some(c), None) => {
+                    i = next;
+                    Token::Number(BigNum::from(c as i64))
+                }
+                _ => {
+                    return Err((
+                        start + 1,
+                        "a character literal should contain one character".to_string(),
+                    ))
+                }
+            }
+        } else if c == '"' {
+            let (value, next) = quoted(&chars, i, '"')?;
+            i = next;
+            Token::Quoted(value)
+        } else if c.is_ascii_digit()
+            || ((c == '-' || c == '+') && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            i += 1;
+            while i < chars.len() && is_word_char(chars[i]) {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            match parse_number(&text) {
+                Some(value) => Token::Number(value),
+                None => return Err((start + 1, format!("{} is not a valid number", text))),
+            }
+        } else if is_word_char(c) {
+            while i < chars.len() && is_word_char(chars[i]) {
+                i += 1;
+            }
+            Token::Word(chars[start..i].iter().collect())
+        } else {
+            return Err((start + 1, format!("{} is an unexpected character", c)));
+        };
+        lexemes.push(Lexeme {
+            token,
+            column: start + 1,
+            end: i + 1,
+        });
+    }
+    Ok(lexemes)
+}
+
+// Reads what is between two quotes starting at `start`, with the usual escapes. Returns the
+// text and the index after the closing quote.
+fn quoted(chars: &[char], start: usize, quote: char) -> Result<(String, usize), (usize, String)> {
+    let mut text = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            c if c == quote => return Ok((text, i + 1)),
+            '\\' => {
+                let escaped = match chars.get(i + 1) {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some(c) if *c == '\\' || *c == '\'' || *c == '"' => *c,
+                    _ => return Err((i + 1, "invalid escape sequence".to_string())),
+                };
+                text.push(escaped);
+                i += 2;
+            }
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err((start + 1, format!("missing closing {}", quote)))
+}
+
+// A decimal or hexadecimal number, with an optional sign
+fn parse_number(text: &str) -> Option<BigNum> {
+    let (negative, unsigned) = match text.chars().next() {
+        Some('-') => (true, &text[1..]),
+        Some('+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let (radix, digits) = if unsigned.starts_with("0x") || unsigned.starts_with("0X") {
+        (16, &unsigned[2..])
+    } else {
+        (10, unsigned)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+
+    let mut value = BigNum::zero();
+    for digit in digits.chars() {
+        let digit = digit.to_digit(radix)?;
+        value = value
+            .mul(&BigNum::from(radix as i32))
+            .add(&BigNum::from(digit as i32));
+    }
+    Some(if negative { value.neg() } else { value })
+}
+
+#[test]
+fn assemble_works() {
+    use Instruction::*;
+
+    let source = "
+        ; every kind of argument
+start:  push 42         ; decimal
+        PUSH -0x2A
+        push '\\n'
+        push 'a'
+        push 18446744073709551616
+        copy 2
+        slide +1
+        label loop
+        \"print string\": dup
+        call \"print string\"
+        jz end
+        jn end$2
+        jmp loop
+        swap
+";
+    assert_eq!(
+        Ok(vec![
+            SetLabel("start".to_string()),
+            Push(42),
+            Push(-42),
+            Push(10),
+            Push(97),
+            PushBig("18446744073709551616".parse().unwrap()),
+            CopyNth(2),
+            Slide(1),
+            SetLabel("loop".to_string()),
+            SetLabel("print string".to_string()),
+            Duplicate,
+            CallSubroutine("print string".to_string()),
+            JZero("end".to_string()),
+            JNeg("end$2".to_string()),
+            Jump("loop".to_string()),
+            Swap,
+        ]),
+        assemble(source)
+    );
+
+    let simple = "dup\nswap\ndrop\nadd\nsub\nmul\ndiv\nmod\nstore\nretrieve\nret\nend\n\
+                  printc\nprinti\nreadc\nreadi";
+    assert_eq!(16, assemble(simple).unwrap().len());
+}
+
+#[test]
+fn assemble_reports_errors() {
+    let error = |line, column, message: &str| {
+        Err(AssembleError {
+            line,
+            column,
+            message: message.to_string(),
+        })
+    };
+    assert_eq!(
+        error(2, 3, "jump is not an instruction"),
+        assemble("dup\n  jump x")
+    );
+    assert_eq!(error(1, 5, "push expects an argument"), assemble("push"));
+    assert_eq!(
+        error(2, 5, "dup takes no argument"),
+        assemble("dup ; ok\ndup 1")
+    );
+    assert_eq!(
+        error(1, 10, "dup takes no argument"),
+        assemble("    dup  1")
+    );
+    assert_eq!(
+        error(1, 9, "push takes one argument"),
+        assemble("push 1  2")
+    );
+    assert_eq!(error(1, 6, "expected a number"), assemble("push x"));
+    assert_eq!(
+        error(1, 6, "12ab is not a valid number"),
+        assemble("push 12ab")
+    );
+    assert_eq!(
+        error(1, 6, "4294967296 does not fit in 32 bits"),
+        assemble("copy 4294967296")
+    );
+    assert_eq!(error(1, 4, "expected a label name"), assemble("jz 1"));
+    assert_eq!(error(1, 6, "missing closing \""), assemble("call \"x"));
+    assert_eq!(
+        error(1, 6, "a character literal should contain one character"),
+        assemble("push 'ab'")
+    );
+    assert_eq!(
+        error(1, 5, "→ can not be written in a label"),
+        assemble("jmp \"→\"")
+    );
+    assert_eq!(
+        error(1, 1, "a label name can not be empty"),
+        assemble("\"\": end")
+    );
+    assert_eq!(error(1, 1, "? is an unexpected character"), assemble("?"));
+}
+
+#[test]
+fn assembled_programs_parse_back() {
+    use crate::dialect::Dialect;
+    use crate::parser::parse_program_with;
+    use crate::parser::ParseOptions;
+
+    let source = "push -7\nl: push 0\ncopy 1\ncall \"ÿ\"\nend";
+    let instructions = assemble(source).unwrap();
+    let encode_options = EncodeOptions {
+        dialect: Dialect::Stl,
+        comments: true,
+    };
+    let ws = assemble_to_ws(source, &encode_options).unwrap();
+    let options = ParseOptions {
+        dialect: Dialect::Stl,
+        ..ParseOptions::default()
+    };
+    assert_eq!(Ok(instructions), parse_program_with(&ws, &options));
+}
+
+#[test]
+fn generated_labels_are_decoded() {
+    assert_eq!(Some("\n\u{5}".to_string()), generated_label("_0a05"));
+    assert_eq!(None, generated_label("_0a0"));
+    assert_eq!(None, generated_label("_0A"));
+    assert_eq!(None, generated_label("_"));
+    assert_eq!(Some("\u{103}".to_string()), generated_label("_.1"));
+    assert_eq!(Some("A\u{105}".to_string()), generated_label("_41.01"));
+    assert_eq!(None, generated_label("_41."));
+    assert_eq!(None, generated_label("_.01010101"));
+    assert_eq!(None, generated_label("_.2"));
+    assert_eq!(
+        Ok(vec![Instruction::Jump("\u{ff}".to_string())]),
+        assemble("jmp _ff")
+    );
+    assert_eq!(
+        Ok(vec![Instruction::Jump("_ff".to_string())]),
+        assemble("jmp \"_ff\"")
+    );
+}
@@ -1,35 +1,397 @@
-struct BigNum {
-    v: Vec<i32>,
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// A signed integer of arbitrary size
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigNum {
+    // the magnitude in base 2^32, small limbs first
+    limbs: Vec<u32>,
+    negative: bool,
 }
 
 impl BigNum {
     // Digits are stored in reverse order (small digits first): [1,2,3] is 321 in base ten
     pub fn new(v: Vec<i32>) -> BigNum {
-        BigNum { v }
+        let mut limbs = Vec::new();
+        for digit in v.iter().rev() {
+            mul_add_small(&mut limbs, 10, *digit as u32);
+        }
+        BigNum::from_limbs(limbs, false)
+    }
+
+    pub fn zero() -> BigNum {
+        BigNum::new(Vec::new())
+    }
+
+    // Zero is never negative and has no limbs, other numbers have no leading zeros
+    fn from_limbs(mut limbs: Vec<u32>, negative: bool) -> BigNum {
+        trim(&mut limbs);
+        let negative = negative && !limbs.is_empty();
+        BigNum { limbs, negative }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn neg(&self) -> BigNum {
+        BigNum::from_limbs(self.limbs.clone(), !self.negative)
+    }
+
+    pub fn abs(&self) -> BigNum {
+        BigNum::from_limbs(self.limbs.clone(), false)
     }
 
     pub fn add(&self, b: &BigNum) -> BigNum {
-        let mut v = Vec::new();
-        let n = self.v.len();
-        let m = b.v.len();
-        let p = n.max(m);
-        v.resize(p, 0);
-        let mut carry = 0;
-        for i in 0..p {
-            let mut t = carry;
-            if i < n {
-                t += self.v[i];
+        if self.negative == b.negative {
+            return BigNum::from_limbs(add_limbs(&self.limbs, &b.limbs), self.negative);
+        }
+        match cmp_limbs(&self.limbs, &b.limbs) {
+            Ordering::Less => BigNum::from_limbs(sub_limbs(&b.limbs, &self.limbs), b.negative),
+            _ => BigNum::from_limbs(sub_limbs(&self.limbs, &b.limbs), self.negative),
+        }
+    }
+
+    pub fn sub(&self, b: &BigNum) -> BigNum {
+        self.add(&b.neg())
+    }
+
+    pub fn mul(&self, b: &BigNum) -> BigNum {
+        if self.is_zero() || b.is_zero() {
+            return BigNum::zero();
+        }
+        let mut limbs = vec![0; self.limbs.len() + b.limbs.len()];
+        for (i, x) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, y) in b.limbs.iter().enumerate() {
+                let t = u64::from(limbs[i + j]) + u64::from(*x) * u64::from(*y) + carry;
+                limbs[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            limbs[i + b.limbs.len()] = carry as u32;
+        }
+        BigNum::from_limbs(limbs, self.negative != b.negative)
+    }
+
+    /// Division rounding towards negative infinity, None when dividing by zero
+    pub fn div(&self, b: &BigNum) -> Option<BigNum> {
+        let (quotient, remainder) = self.div_rem(b)?;
+        if !remainder.is_zero() && self.negative != b.negative {
+            Some(quotient.sub(&BigNum::from(1)))
+        } else {
+            Some(quotient)
+        }
+    }
+
+    /// Modulo with the sign of the divisor, None when dividing by zero
+    pub fn modulo(&self, b: &BigNum) -> Option<BigNum> {
+        let (_, remainder) = self.div_rem(b)?;
+        if !remainder.is_zero() && remainder.negative != b.negative {
+            Some(remainder.add(b))
+        } else {
+            Some(remainder)
+        }
+    }
+
+    // Truncated division: the remainder has the sign of the dividend
+    fn div_rem(&self, b: &BigNum) -> Option<(BigNum, BigNum)> {
+        if b.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = div_rem_limbs(&self.limbs, &b.limbs);
+        Some((
+            BigNum::from_limbs(quotient, self.negative != b.negative),
+            BigNum::from_limbs(remainder, self.negative),
+        ))
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0i128, |value, limb| value << 32 | i128::from(*limb));
+        let value = if self.negative { -magnitude } else { magnitude };
+        i64::try_from(value).ok()
+    }
+
+    pub fn to_i32(&self) -> Option<i32> {
+        self.to_i64().and_then(|v| {
+            if v >= i64::from(i32::MIN) && v <= i64::from(i32::MAX) {
+                Some(v as i32)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The low 32 bits of the two's complement representation
+    pub fn wrapping_to_i32(&self) -> i32 {
+        let low_bits = self.limbs.first().cloned().unwrap_or(0);
+        if self.negative {
+            low_bits.wrapping_neg() as i32
+        } else {
+            low_bits as i32
+        }
+    }
+
+    // The decimal digits, small digits first like `new` takes them
+    #[cfg(test)]
+    fn digits(&self) -> Vec<i32> {
+        if self.is_zero() {
+            return Vec::new();
+        }
+        let text = self.abs().to_string();
+        text.bytes().rev().map(|b| i32::from(b - b'0')).collect()
+    }
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn cmp_limbs(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() < b.len() { (b, a) } else { (a, b) };
+    let mut limbs = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0;
+    for (i, x) in long.iter().enumerate() {
+        let t = u64::from(*x) + u64::from(short.get(i).cloned().unwrap_or(0)) + carry;
+        limbs.push(t as u32);
+        carry = t >> 32;
+    }
+    limbs.push(carry as u32);
+    trim(&mut limbs);
+    limbs
+}
+
+// a - b, where a >= b
+fn sub_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, x) in a.iter().enumerate() {
+        let t = i64::from(*x) - i64::from(b.get(i).cloned().unwrap_or(0)) - borrow;
+        limbs.push(t as u32);
+        borrow = if t < 0 { 1 } else { 0 };
+    }
+    trim(&mut limbs);
+    limbs
+}
+
+// limbs * m + a
+fn mul_add_small(limbs: &mut Vec<u32>, m: u32, a: u32) {
+    let mut carry = u64::from(a);
+    for limb in limbs.iter_mut() {
+        let t = u64::from(*limb) * u64::from(m) + carry;
+        *limb = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        limbs.push(carry as u32);
+    }
+}
+
+// The quotient and the remainder of a division by a single limb
+fn div_rem_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0; a.len()];
+    let mut remainder = 0u64;
+    for (i, x) in a.iter().enumerate().rev() {
+        let t = remainder << 32 | u64::from(*x);
+        quotient[i] = (t / u64::from(d)) as u32;
+        remainder = t % u64::from(d);
+    }
+    trim(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+// The limbs shifted left by less than 32 bits, with one more limb
+fn shift_left(a: &[u32], shift: u32) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for x in a.iter() {
+        let t = u64::from(*x) << shift | carry;
+        limbs.push(t as u32);
+        carry = t >> 32;
+    }
+    limbs.push(carry as u32);
+    limbs
+}
+
+// The limbs shifted right by less than 32 bits
+fn shift_right(a: &[u32], shift: u32) -> Vec<u32> {
+    let mut limbs: Vec<u32> = (0..a.len())
+        .map(|i| {
+            let high = u64::from(a.get(i + 1).cloned().unwrap_or(0));
+            ((high << 32 | u64::from(a[i])) >> shift) as u32
+        })
+        .collect();
+    trim(&mut limbs);
+    limbs
+}
+
+// Schoolbook long division of magnitudes, b not being zero (Knuth's algorithm D). Both are
+// shifted so that the top limb of b has its high bit set, which makes every guessed limb of
+// the quotient at most 2 too large.
+fn div_rem_limbs(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_limbs(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = div_rem_small(a, b[0]);
+        let mut remainder = vec![remainder];
+        trim(&mut remainder);
+        return (quotient, remainder);
+    }
+    let shift = b[b.len() - 1].leading_zeros();
+    let mut b = shift_left(b, shift);
+    b.pop();
+    let mut a = shift_left(a, shift);
+    let n = b.len();
+    let top = u64::from(b[n - 1]);
+    let next = u64::from(b[n - 2]);
+    let mut quotient = vec![0; a.len() - n];
+
+    for j in (0..quotient.len()).rev() {
+        let t = u64::from(a[j + n]) << 32 | u64::from(a[j + n - 1]);
+        let mut guess = t / top;
+        let mut rest = t % top;
+        let third = u64::from(a[j + n - 2]);
+        while guess > u64::from(u32::MAX) || guess * next > (rest << 32 | third) {
+            guess -= 1;
+            rest += top;
+            if rest > u64::from(u32::MAX) {
+                break;
             }
-            if i < m {
-                t += b.v[i];
+        }
+
+        // a -= guess * b, shifted by j limbs
+        let mut borrow = 0;
+        let mut carry = 0;
+        for i in 0..n {
+            let product = guess * u64::from(b[i]) + carry;
+            carry = product >> 32;
+            let t = i64::from(a[i + j]) - i64::from(product as u32) - borrow;
+            a[i + j] = t as u32;
+            borrow = if t < 0 { 1 } else { 0 };
+        }
+        let t = i64::from(a[j + n]) - carry as i64 - borrow;
+        a[j + n] = t as u32;
+
+        // the guess was one too large, add b back
+        if t < 0 {
+            guess -= 1;
+            let mut carry = 0;
+            for i in 0..n {
+                let t = u64::from(a[i + j]) + u64::from(b[i]) + carry;
+                a[i + j] = t as u32;
+                carry = t >> 32;
             }
-            v[i] = t % 10;
-            carry = t / 10;
+            a[j + n] = a[j + n].wrapping_add(carry as u32);
         }
-        if carry > 0 {
-            v.push(1);
+        quotient[j] = guess as u32;
+    }
+    trim(&mut quotient);
+    (quotient, shift_right(&a[..n], shift))
+}
+
+impl From<i64> for BigNum {
+    fn from(value: i64) -> BigNum {
+        let magnitude = value.unsigned_abs();
+        BigNum::from_limbs(vec![magnitude as u32, (magnitude >> 32) as u32], value < 0)
+    }
+}
+
+impl From<i32> for BigNum {
+    fn from(value: i32) -> BigNum {
+        BigNum::from(i64::from(value))
+    }
+}
+
+impl Ord for BigNum {
+    fn cmp(&self, other: &BigNum) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_limbs(&self.limbs, &other.limbs),
+            (true, true) => cmp_limbs(&other.limbs, &self.limbs),
         }
-        BigNum::new(v)
+    }
+}
+
+impl PartialOrd for BigNum {
+    fn partial_cmp(&self, other: &BigNum) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Nine decimal digits at a time
+const CHUNK: u32 = 1_000_000_000;
+
+impl fmt::Display for BigNum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = Vec::new();
+        let mut rest = self.limbs.clone();
+        while !rest.is_empty() {
+            let (quotient, chunk) = div_rem_small(&rest, CHUNK);
+            chunks.push(chunk);
+            rest = quotient;
+        }
+        write!(f, "{}", chunks[chunks.len() - 1])?;
+        for chunk in chunks.iter().rev().skip(1) {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseBigNumError;
+
+impl FromStr for BigNum {
+    type Err = ParseBigNumError;
+
+    // An optional sign followed by decimal digits
+    fn from_str(s: &str) -> Result<BigNum, ParseBigNumError> {
+        let (negative, digits) = match s.chars().next() {
+            Some('-') => (true, &s[1..]),
+            Some('+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        if digits.is_empty() {
+            return Err(ParseBigNumError);
+        }
+        let mut limbs = Vec::new();
+        for chunk in digits.as_bytes().chunks(9) {
+            let mut value = 0;
+            for c in chunk.iter() {
+                let digit = (*c as char).to_digit(10).ok_or(ParseBigNumError)?;
+                value = value * 10 + digit;
+            }
+            mul_add_small(&mut limbs, 10u32.pow(chunk.len() as u32), value);
+        }
+        Ok(BigNum::from_limbs(limbs, negative))
     }
 }
 
@@ -38,7 +400,7 @@ fn instruction_push_nominal() {
     let a = BigNum::new(vec![3, 2, 1]); // 123
     let b = BigNum::new(vec![4, 5, 6]); // 654
     let c = a.add(&b);
-    assert_eq!(vec![7, 7, 7], c.v);
+    assert_eq!(vec![7, 7, 7], c.digits());
 }
 
 #[test]
@@ -46,5 +408,123 @@ fn instruction_push_nominal2() {
     let a = BigNum::new(vec![2, 1]); // 12
     let b = BigNum::new(vec![4, 3, 7]); // 732
     let c = a.add(&b);
-    assert_eq!(vec![6, 4, 7], c.v);
+    assert_eq!(vec![6, 4, 7], c.digits());
+}
+
+#[test]
+fn signed_arithmetic_works() {
+    let big = |s: &str| s.parse::<BigNum>().unwrap();
+
+    assert_eq!(big("-5"), big("7").add(&big("-12")));
+    assert_eq!(big("19"), big("7").sub(&big("-12")));
+    assert_eq!(big("0"), big("-12").sub(&big("-12")));
+    assert!(!big("-12").sub(&big("-12")).is_negative());
+    assert_eq!(
+        big("121932631137021795226185032733622923332237463801111263526900"),
+        big("123456789012345678901234567890").mul(&big("987654321098765432109876543210"))
+    );
+    assert_eq!(big("-84"), big("-7").mul(&big("12")));
+    assert_eq!(
+        big("-999999999999999999999"),
+        big("999999999999999999999").neg()
+    );
+}
+
+#[test]
+fn floor_division_works() {
+    let big = |s: &str| s.parse::<BigNum>().unwrap();
+
+    assert_eq!(Some(big("3")), big("7").div(&big("2")));
+    assert_eq!(Some(big("-4")), big("-7").div(&big("2")));
+    assert_eq!(Some(big("-4")), big("7").div(&big("-2")));
+    assert_eq!(Some(big("3")), big("-7").div(&big("-2")));
+    assert_eq!(Some(big("1")), big("7").modulo(&big("2")));
+    assert_eq!(Some(big("1")), big("-7").modulo(&big("2")));
+    assert_eq!(Some(big("-1")), big("7").modulo(&big("-2")));
+    assert_eq!(Some(big("-1")), big("-7").modulo(&big("-2")));
+    assert_eq!(None, big("7").div(&big("0")));
+    assert_eq!(None, big("7").modulo(&big("0")));
+    let a = big("12345678901234567890");
+    let b = big("-9999999999999999999");
+    assert_eq!(Some(a.clone()), a.mul(&b).div(&b));
+    assert_eq!(Some(b.add(&big("1"))), a.mul(&b).add(&big("1")).modulo(&b));
+    assert_eq!(
+        Some(big("1000000000000000000000")),
+        big("1000000000000000000000000").div(&big("1000"))
+    );
+}
+
+#[test]
+fn conversions_work() {
+    assert_eq!("0", BigNum::zero().to_string());
+    assert_eq!("-42", BigNum::from(-42).to_string());
+    assert_eq!("-9223372036854775808", BigNum::from(i64::MIN).to_string());
+    assert_eq!(Some(i64::MIN), BigNum::from(i64::MIN).to_i64());
+    assert_eq!(Some(i64::MAX), BigNum::from(i64::MAX).to_i64());
+    assert_eq!(
+        None,
+        "9223372036854775808".parse::<BigNum>().unwrap().to_i64()
+    );
+    assert_eq!(Some(i32::MIN), BigNum::from(i32::MIN).to_i32());
+    assert_eq!(None, BigNum::from(1i64 << 31).to_i32());
+    assert_eq!(i32::MIN, BigNum::from(1i64 << 31).wrapping_to_i32());
+    assert_eq!(-1, BigNum::from(-1).wrapping_to_i32());
+    assert_eq!(-2, BigNum::from((1i64 << 33) - 2).wrapping_to_i32());
+    assert!(BigNum::from(-3) < BigNum::from(2));
+    assert!(BigNum::from(-3) < BigNum::from(-2));
+    assert_eq!(Err(ParseBigNumError), "12a".parse::<BigNum>());
+    assert_eq!(Err(ParseBigNumError), "-".parse::<BigNum>());
+}
+
+#[test]
+fn long_division_works() {
+    let big = |v: i128| v.to_string().parse::<BigNum>().unwrap();
+
+    // compared with i128, with divisors of one to three limbs and limbs close to 2^32
+    let mut seed: u64 = 1;
+    let mut next = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let value = (i128::from(seed >> 1) << 62) ^ i128::from(seed);
+        match seed % 4 {
+            0 => value >> (seed % 120),
+            1 => value | 0xFFFF_FFFF,
+            2 => -(value >> 64),
+            _ => value >> 90,
+        }
+    };
+    // both need the guess to be corrected after the subtraction
+    let cases = [
+        (
+            0x7FFF_FFFF_8000_0000_0000_0000_0000_0000,
+            0x8000_0000_0000_0000_0000_0001,
+        ),
+        (
+            0x8000_0000_0000_0000_0000_0003,
+            0x2000_0000_0000_0000_0000_0001,
+        ),
+    ];
+    let random: Vec<(i128, i128)> = (0..2000).map(|_| (next(), next())).collect();
+    for (a, b) in cases.iter().chain(random.iter()).cloned() {
+        if b == 0 {
+            continue;
+        }
+        let rounded_up = a % b != 0 && (a < 0) != (b < 0);
+        let floor = if rounded_up { a / b - 1 } else { a / b };
+        let context = format!("{} {}", a, b);
+        assert_eq!(Some(big(floor)), big(a).div(&big(b)), "{}", context);
+        let remainder = a - floor * b;
+        assert_eq!(Some(big(remainder)), big(a).modulo(&big(b)), "{}", context);
+    }
+
+    let a = big(0x7FFF_FFFF_0000_0000_0000_0000_0000_0001);
+    assert_eq!(
+        Some(big(0x7FFF_FFFF_0000_0000)),
+        a.div(&big(0x1_0000_0000_0000_0000))
+    );
+    assert_eq!(
+        "340282366920938463463374607431768211455",
+        big(i128::MAX).mul(&big(2)).add(&big(1)).to_string()
+    );
 }
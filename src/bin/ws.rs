@@ -0,0 +1,464 @@
+extern crate whitespace;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::process;
+use structopt::StructOpt;
+
+use whitespace::arithmetic::{IntegerMode, Number, Promoted};
+use whitespace::assembler::assemble;
+use whitespace::bignum::BigNum;
+use whitespace::c_backend::generate_c;
+use whitespace::compiler::{generate_standalone_source_code, write_source_code, CompileError};
+use whitespace::dialect::Dialect;
+use whitespace::disassembler::{disassemble, disassemble_instruction};
+use whitespace::encoder::{encode_program_with, EncodeOptions};
+use whitespace::instruction::Instruction;
+use whitespace::linker;
+use whitespace::parser::*;
+use whitespace::vm::*;
+
+// Exit codes, besides 0 when everything went well
+const EXIT_IO_ERROR: i32 = 1;
+const EXIT_PARSE_ERROR: i32 = 2;
+const EXIT_RUNTIME_ERROR: i32 = 3;
+const EXIT_BUILD_ERROR: i32 = 4;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "ws",
+    about = "Runs and transforms whitespace programs",
+    after_help = "Exit codes: 1 when a file can not be read or written, 2 when a program \
+                  can not be parsed, assembled or linked, 3 when a program fails at runtime, \
+                  4 when a program can not be built into an executable."
+)]
+enum Opt {
+    /// Runs a program
+    #[structopt(name = "run")]
+    Run {
+        #[structopt(flatten)]
+        program: Program,
+        #[structopt(flatten)]
+        input: Input,
+        /// Compile the program to machine code first, when ws is built with the jit feature
+        #[structopt(long)]
+        jit: bool,
+    },
+    /// Reports every error of a program, without running it
+    #[structopt(name = "check")]
+    Check(Program),
+    /// Turns an assembly program into a whitespace program
+    #[structopt(name = "asm")]
+    Asm {
+        #[structopt(flatten)]
+        program: Program,
+        /// Write the assembly of every instruction as a comment
+        #[structopt(long)]
+        comments: bool,
+    },
+    /// Turns a whitespace program into assembly
+    #[structopt(name = "disasm")]
+    Disasm(Program),
+    /// Rewrites a whitespace program without its comments, with the shortest numbers
+    #[structopt(name = "fmt")]
+    Fmt {
+        #[structopt(flatten)]
+        program: Program,
+        /// Write the assembly of every instruction as a comment
+        #[structopt(long)]
+        comments: bool,
+        /// The dialect to write, the one of the program by default
+        #[structopt(long)]
+        to: Option<Dialect>,
+    },
+    /// Turns a whitespace program into a rust program
+    #[structopt(name = "compile")]
+    Compile {
+        #[structopt(flatten)]
+        program: Program,
+        /// Write a program that does not need the whitespace crate, with 32 bits integers
+        #[structopt(long)]
+        standalone: bool,
+    },
+    /// Compiles a program to a native executable, through C
+    #[structopt(name = "build")]
+    Build {
+        #[structopt(flatten)]
+        program: Program,
+        /// The C compiler, $CC or cc by default
+        #[structopt(long)]
+        cc: Option<String>,
+        /// Write the C source instead of an executable
+        #[structopt(long = "emit-c")]
+        emit_c: bool,
+    },
+    /// Runs a program, printing the stack before and after every instruction
+    #[structopt(name = "debug")]
+    Debug {
+        #[structopt(flatten)]
+        program: Program,
+        #[structopt(flatten)]
+        input: Input,
+    },
+    /// Runs a program, printing every instruction executed and where it comes from
+    #[structopt(name = "trace")]
+    Trace {
+        #[structopt(flatten)]
+        program: Program,
+        #[structopt(flatten)]
+        input: Input,
+    },
+}
+
+// The flags shared by every subcommand
+#[derive(StructOpt, Debug)]
+struct Program {
+    /// How the program is written: standard, crlf, stl, abc or custom:XYZ
+    #[structopt(long, default_value = "standard")]
+    dialect: Dialect,
+    /// What happens when a number does not fit in 32 bits: checked, wrapping, saturating, or
+    /// promote to arbitrary precision
+    #[structopt(long = "integer-mode", default_value = "checked")]
+    integer_mode: IntegerMode,
+    /// Use arbitrary precision integers instead of 32 bits ones
+    #[structopt(short, long)]
+    bignum: bool,
+    /// Read numbers made of a sign only as 0, like the reference implementation
+    #[structopt(long = "allow-sign-only")]
+    allow_sign_only: bool,
+    /// Write the output to this file instead of stdout
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Input file
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file: PathBuf,
+}
+
+// The flags of the subcommands that run the program
+#[derive(StructOpt, Debug)]
+struct Input {
+    /// Read the input of the program from this file instead of stdin
+    #[structopt(short, long, parse(from_os_str))]
+    input: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Run,
+    Jit,
+    Debug,
+    Trace,
+}
+
+fn main() {
+    let result = match Opt::from_args() {
+        Opt::Run {
+            program,
+            input,
+            jit,
+        } => execute(&program, &input, if jit { Mode::Jit } else { Mode::Run }),
+        Opt::Check(program) => check(&program),
+        Opt::Asm { program, comments } => asm(&program, comments),
+        Opt::Disasm(program) => parse(&program)
+            .and_then(|instructions| write(&program, disassemble(&instructions).as_bytes())),
+        Opt::Fmt {
+            program,
+            comments,
+            to,
+        } => parse(&program).and_then(|instructions| {
+            let options = EncodeOptions {
+                dialect: to.unwrap_or_else(|| program.dialect.clone()),
+                comments,
+            };
+            write(
+                &program,
+                encode_program_with(&instructions, &options).as_bytes(),
+            )
+        }),
+        Opt::Compile {
+            program,
+            standalone,
+        } => compile(&program, standalone),
+        Opt::Build {
+            program,
+            cc,
+            emit_c,
+        } => build(&program, cc, emit_c),
+        Opt::Debug { program, input } => execute(&program, &input, Mode::Debug),
+        Opt::Trace { program, input } => execute(&program, &input, Mode::Trace),
+    };
+    if let Err(code) = result {
+        process::exit(code);
+    }
+}
+
+fn fail<T>(file: &Path, error: impl std::fmt::Display, code: i32) -> Result<T, i32> {
+    eprintln!("{}: {}", file.display(), error);
+    Err(code)
+}
+
+// A program that does not link is a parse error, one the backend does not support a build error
+fn compile_failure<T>(file: &Path, error: CompileError) -> Result<T, i32> {
+    let code = match error {
+        CompileError::Link(_) => EXIT_PARSE_ERROR,
+        CompileError::UnsupportedIntegerMode(_) => EXIT_BUILD_ERROR,
+    };
+    fail(file, error, code)
+}
+
+fn read(file: &Path) -> Result<String, i32> {
+    let mut contents = String::new();
+    match File::open(file).and_then(|mut f| f.read_to_string(&mut contents)) {
+        Ok(_) => Ok(contents),
+        Err(e) => fail(file, e, EXIT_IO_ERROR),
+    }
+}
+
+fn write(program: &Program, contents: &[u8]) -> Result<(), i32> {
+    let result = match &program.output {
+        Some(path) => File::create(path).and_then(|mut f| f.write_all(contents)),
+        None => io::stdout().write_all(contents),
+    };
+    let path = program
+        .output
+        .as_deref()
+        .unwrap_or_else(|| Path::new("stdout"));
+    result.or_else(|e| fail(path, e, EXIT_IO_ERROR))
+}
+
+fn parse_options(program: &Program) -> ParseOptions {
+    ParseOptions {
+        integer_mode: program.integer_mode,
+        arbitrary_precision: program.bignum,
+        allow_sign_only_numbers: program.allow_sign_only,
+        dialect: program.dialect.clone(),
+    }
+}
+
+fn parse_spanned(program: &Program) -> Result<Vec<SpannedInstruction>, i32> {
+    let source = read(&program.file)?;
+    match parse_program_spanned(&source, &parse_options(program)) {
+        Ok(instructions) => Ok(instructions),
+        Err(e) => fail(&program.file, e, EXIT_PARSE_ERROR),
+    }
+}
+
+fn parse(program: &Program) -> Result<Vec<Instruction>, i32> {
+    parse_spanned(program).map(|instructions| without_spans(&instructions))
+}
+
+fn without_spans(program: &[SpannedInstruction]) -> Vec<Instruction> {
+    program.iter().map(|s| s.instruction.clone()).collect()
+}
+
+fn asm(program: &Program, comments: bool) -> Result<(), i32> {
+    let source = read(&program.file)?;
+    match assemble(&source) {
+        Ok(instructions) => {
+            let options = EncodeOptions {
+                dialect: program.dialect.clone(),
+                comments,
+            };
+            write(
+                program,
+                encode_program_with(&instructions, &options).as_bytes(),
+            )
+        }
+        Err(e) => fail(&program.file, e, EXIT_PARSE_ERROR),
+    }
+}
+
+fn compile(program: &Program, standalone: bool) -> Result<(), i32> {
+    let instructions = parse(program)?;
+    if standalone {
+        if program.bignum || program.integer_mode == IntegerMode::Promote {
+            return fail(
+                &program.file,
+                "standalone programs only support 32 bits integers",
+                EXIT_BUILD_ERROR,
+            );
+        }
+        return match generate_standalone_source_code(&instructions, program.integer_mode) {
+            Ok(source) => write(program, source.as_bytes()),
+            Err(e) => compile_failure(&program.file, e),
+        };
+    }
+    let mut source = Vec::new();
+    write_source_code(&instructions, &mut source).expect("writing to memory can not fail");
+    write(program, &source)
+}
+
+// Writes the program as C, then compiles it unless emit_c is set. The executable is named
+// after the program when there is no output file.
+fn build(program: &Program, cc: Option<String>, emit_c: bool) -> Result<(), i32> {
+    if program.bignum || program.integer_mode == IntegerMode::Promote {
+        return fail(
+            &program.file,
+            "the C backend only supports 32 bits integers",
+            EXIT_BUILD_ERROR,
+        );
+    }
+    let instructions = parse(program)?;
+    let source = match generate_c(&instructions, program.integer_mode) {
+        Ok(source) => source,
+        Err(e) => return compile_failure(&program.file, e),
+    };
+    if emit_c {
+        return write(program, source.as_bytes());
+    }
+
+    let executable = program
+        .output
+        .clone()
+        .unwrap_or_else(|| program.file.with_extension(""));
+    let c_file = std::env::temp_dir().join(format!("ws-{}.c", process::id()));
+    if let Err(e) = std::fs::write(&c_file, source) {
+        return fail(&c_file, e, EXIT_IO_ERROR);
+    }
+    let cc = cc
+        .or_else(|| std::env::var("CC").ok())
+        .unwrap_or_else(|| "cc".to_string());
+    let mut words = cc.split_whitespace();
+    let status = process::Command::new(words.next().unwrap_or("cc"))
+        .args(words)
+        .arg("-O2")
+        .arg("-o")
+        .arg(&executable)
+        .arg(&c_file)
+        .status();
+    let _ = std::fs::remove_file(&c_file);
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => fail(
+            &program.file,
+            format!("{} failed: {}", cc, status),
+            EXIT_BUILD_ERROR,
+        ),
+        Err(e) => fail(&program.file, format!("{}: {}", cc, e), EXIT_BUILD_ERROR),
+    }
+}
+
+// The labels defined twice and the branches to labels defined nowhere in the program
+fn link_errors(program: &[SpannedInstruction]) -> Vec<String> {
+    linker::link_errors(&without_spans(program))
+        .iter()
+        .map(|e| {
+            let span = &program[e.ip].span;
+            format!("{} at line {}, column {}", e.kind, span.line, span.column)
+        })
+        .collect()
+}
+
+fn check(program: &Program) -> Result<(), i32> {
+    let source = read(&program.file)?;
+    let (instructions, errors) = parse_program_recovering(&source, &parse_options(program));
+    let mut errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    errors.extend(link_errors(&instructions));
+    for e in errors.iter() {
+        eprintln!("{}: {}", program.file.display(), e);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(EXIT_PARSE_ERROR)
+    }
+}
+
+fn execute(program: &Program, input: &Input, mode: Mode) -> Result<(), i32> {
+    let instructions = parse_spanned(program)?;
+    if let Some(e) = link_errors(&instructions).first() {
+        return fail(&program.file, e, EXIT_PARSE_ERROR);
+    }
+    let input: Box<dyn BufRead> = match &input.input {
+        Some(path) => match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => return fail(path, e, EXIT_IO_ERROR),
+        },
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+    let output: Box<dyn Write> = match &program.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(io::BufWriter::new(file)),
+            Err(e) => return fail(path, e, EXIT_IO_ERROR),
+        },
+        None => Box::new(io::stdout()),
+    };
+
+    let result = if program.bignum {
+        run::<BigNum>(&instructions, program, mode, input, output)
+    } else if program.integer_mode == IntegerMode::Promote {
+        run::<Promoted>(&instructions, program, mode, input, output)
+    } else if mode == Mode::Jit {
+        run_jit(&instructions, program, input, output)
+    } else {
+        run::<i32>(&instructions, program, mode, input, output)
+    };
+    result.or_else(|e| match instructions.get(e.ip) {
+        Some(s) => fail(
+            &program.file,
+            format!("{} at line {}, column {}", e, s.span.line, s.span.column),
+            EXIT_RUNTIME_ERROR,
+        ),
+        None => fail(&program.file, e, EXIT_RUNTIME_ERROR),
+    })
+}
+
+fn run<N: Number>(
+    program: &[SpannedInstruction],
+    options: &Program,
+    mode: Mode,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+) -> Result<(), RuntimeError> {
+    let mut vm = VirtualMachine::<_, _, N>::load(&without_spans(program), input, output);
+    vm.integer_mode = options.integer_mode;
+    vm.debug = mode == Mode::Debug;
+    if mode != Mode::Trace || program.is_empty() {
+        return vm.run_program();
+    }
+
+    let mut result = Ok(());
+    while vm.instructions().get(vm.ip) != Some(&Instruction::EndOfProgram) {
+        if let Some(s) = program.get(vm.ip) {
+            eprintln!(
+                "{}:{}\t{}",
+                s.span.line,
+                s.span.column,
+                disassemble_instruction(&s.instruction).trim()
+            );
+        }
+        result = vm.run_instruction();
+        if result.is_err() {
+            break;
+        }
+        let stack: Vec<String> = vm.stack.iter().map(|v| v.to_string()).collect();
+        eprintln!("\t[{}]", stack.join(", "));
+    }
+    let _ = vm.output.flush();
+    result
+}
+
+#[cfg(feature = "jit")]
+fn run_jit(
+    program: &[SpannedInstruction],
+    options: &Program,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+) -> Result<(), RuntimeError> {
+    let mut vm = VirtualMachine::load(&without_spans(program), input, output);
+    vm.integer_mode = options.integer_mode;
+    vm.run_jit()
+}
+
+#[cfg(not(feature = "jit"))]
+fn run_jit(
+    program: &[SpannedInstruction],
+    options: &Program,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+) -> Result<(), RuntimeError> {
+    eprintln!("ws was built without the jit feature, running the program without it");
+    run::<i32>(program, options, Mode::Run, input, output)
+}
@@ -0,0 +1,118 @@
+use crate::bignum::BigNum;
+use crate::instruction::Instruction;
+use crate::linker;
+
+/// An instruction lowered for the virtual machine: it is `Copy`, its operands are inline and
+/// its branch target is already resolved. There is one `Op` per instruction, so an index in
+/// the bytecode is also an index in the program.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Push(i32),
+    /// The index of the value in `Bytecode::constants`
+    PushConst(usize),
+    Duplicate,
+    CopyNth(i32),
+    Swap,
+    Discard,
+    Slide(i32),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Store,
+    Retrieve,
+    /// A label definition, which does nothing
+    Nop,
+    // The index of the instruction to go to, None for an unknown label
+    Call(Option<usize>),
+    Jump(Option<usize>),
+    JZero(Option<usize>),
+    JNeg(Option<usize>),
+    Return,
+    End,
+    PrintChar,
+    PrintInt,
+    ReadChar,
+    ReadInt,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Bytecode {
+    pub ops: Vec<Op>,
+    /// The literals too large for `Op::Push`
+    pub constants: Vec<BigNum>,
+}
+
+impl Bytecode {
+    pub fn new(instructions: &[Instruction]) -> Bytecode {
+        Bytecode::compile(instructions, &linker::resolve(instructions))
+    }
+
+    /// Lowers instructions whose branch targets were resolved by the linker
+    pub fn compile(instructions: &[Instruction], targets: &[Option<usize>]) -> Bytecode {
+        let mut constants = Vec::new();
+        let ops = instructions
+            .iter()
+            .zip(targets.iter())
+            .map(|(instruction, target)| match instruction {
+                Instruction::Push(v) => Op::Push(*v),
+                Instruction::PushBig(v) => {
+                    constants.push(v.clone());
+                    Op::PushConst(constants.len() - 1)
+                }
+                Instruction::Duplicate => Op::Duplicate,
+                Instruction::CopyNth(v) => Op::CopyNth(*v),
+                Instruction::Swap => Op::Swap,
+                Instruction::Discard => Op::Discard,
+                Instruction::Slide(v) => Op::Slide(*v),
+                Instruction::Add => Op::Add,
+                Instruction::Sub => Op::Sub,
+                Instruction::Mul => Op::Mul,
+                Instruction::Div => Op::Div,
+                Instruction::Mod => Op::Mod,
+                Instruction::Store => Op::Store,
+                Instruction::Retrieve => Op::Retrieve,
+                Instruction::SetLabel(_) => Op::Nop,
+                Instruction::CallSubroutine(_) => Op::Call(*target),
+                Instruction::Jump(_) => Op::Jump(*target),
+                Instruction::JZero(_) => Op::JZero(*target),
+                Instruction::JNeg(_) => Op::JNeg(*target),
+                Instruction::EndOfSubroutine => Op::Return,
+                Instruction::EndOfProgram => Op::End,
+                Instruction::PrintChar => Op::PrintChar,
+                Instruction::PrintInt => Op::PrintInt,
+                Instruction::ReadChar => Op::ReadChar,
+                Instruction::ReadInt => Op::ReadInt,
+            })
+            .collect();
+        Bytecode { ops, constants }
+    }
+}
+
+#[test]
+fn bytecode_has_one_op_per_instruction() {
+    use Instruction::*;
+
+    let big: BigNum = "18446744073709551616".parse().unwrap();
+    let bytecode = Bytecode::new(&[
+        SetLabel("a".to_string()),
+        PushBig(big.clone()),
+        Push(-1),
+        JNeg("a".to_string()),
+        Jump("b".to_string()),
+        EndOfProgram,
+    ]);
+    assert_eq!(
+        vec![
+            Op::Nop,
+            Op::PushConst(0),
+            Op::Push(-1),
+            Op::JNeg(Some(0)),
+            Op::Jump(None),
+            Op::End
+        ],
+        bytecode.ops
+    );
+    assert_eq!(vec![big], bytecode.constants);
+}
@@ -0,0 +1,203 @@
+use crate::arithmetic::{IntegerMode, Number};
+use crate::compiler::CompileError;
+use crate::disassembler::disassemble_instruction;
+use crate::instruction::Instruction;
+use crate::linker;
+
+// The functions every program uses, see runtime.c
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// Writes a C program that behaves like the virtual machine running the instructions with 32
+/// bits integers. Every branch is a `goto`, and a call pushes the place to return to on a call
+/// stack. The program only needs the C standard library, and exits with 3 after a runtime error.
+pub fn generate_c(instructions: &[Instruction], mode: IntegerMode) -> Result<String, CompileError> {
+    let mode_name = match mode {
+        IntegerMode::Checked => "CHECKED",
+        IntegerMode::Wrapping => "WRAPPING",
+        IntegerMode::Saturating => "SATURATING",
+        IntegerMode::Promote => return Err(CompileError::UnsupportedIntegerMode(mode)),
+    };
+    let targets = linker::link(instructions)?;
+    let mut is_target = vec![false; instructions.len()];
+    for target in targets.iter().flatten() {
+        is_target[*target] = true;
+    }
+
+    let mut source = String::from("/* Generated from a whitespace program */\n");
+    source += &format!(
+        "#define MODE {}\n#define COUNT ((size_t){})\n",
+        mode_name,
+        instructions.len()
+    );
+    // how instructions are shown in error messages, and an empty one as a C array can not be
+    // empty
+    source += "static const char *const INSTRUCTIONS[] = {\n";
+    for instruction in instructions.iter() {
+        source += &format!("    {},\n", c_string(&format!("{:?}", instruction)));
+    }
+    source += "    \"\"\n};\n\n";
+    source += RUNTIME;
+
+    source += "\nint main(void)\n{\n";
+    if instructions.is_empty() {
+        source += "    return 0;\n}\n";
+        return Ok(source);
+    }
+    let mut calls = 0;
+    let mut returns = false;
+    for (ip, instruction) in instructions.iter().enumerate() {
+        let assembly = disassemble_instruction(instruction).replace("*/", "* /");
+        source += &format!("    /* {} */\n", assembly.trim());
+        if is_target[ip] {
+            source += &format!("l{}:\n", ip);
+        }
+        let target = targets[ip].unwrap_or_default();
+        let code = match instruction {
+            Instruction::Push(v) => format!("push({});", c_int(*v)),
+            Instruction::PushBig(v) => match i32::from_bignum(v, mode) {
+                Ok(v) => format!("push({});", c_int(v)),
+                Err(_) => format!("fail({}, \"arithmetic overflow\");", ip),
+            },
+            Instruction::Duplicate => format!("need({}, 1);\n    push(stack[len - 1]);", ip),
+            Instruction::CopyNth(v) | Instruction::Slide(v) if *v < 0 => {
+                format!("fail({}, \"negative argument {}\");", ip, v)
+            }
+            Instruction::CopyNth(v) => format!(
+                "need({}, {});\n    push(stack[len - {}]);",
+                ip,
+                i64::from(*v) + 1,
+                i64::from(*v) + 1
+            ),
+            Instruction::Swap => format!(
+                "need({}, 2);\n    {{\n        int32_t top = stack[len - 1];\n        \
+                 stack[len - 1] = stack[len - 2];\n        stack[len - 2] = top;\n    }}",
+                ip
+            ),
+            Instruction::Discard => format!("need({}, 1);\n    len--;", ip),
+            Instruction::Slide(v) => format!(
+                "need({}, {});\n    stack[len - {}] = stack[len - 1];\n    len -= {};",
+                ip,
+                i64::from(*v) + 1,
+                i64::from(*v) + 1,
+                v
+            ),
+            Instruction::Add => format!("add({});", ip),
+            Instruction::Sub => format!("sub({});", ip),
+            Instruction::Mul => format!("mul({});", ip),
+            Instruction::Div => format!("divide({});", ip),
+            Instruction::Mod => format!("modulo({});", ip),
+            Instruction::Store => format!("store({});", ip),
+            Instruction::Retrieve => format!("retrieve({});", ip),
+            Instruction::SetLabel(_) => continue,
+            Instruction::CallSubroutine(_) => {
+                calls += 1;
+                format!("call({}); goto l{};\nr{}:", calls - 1, target, calls - 1)
+            }
+            Instruction::Jump(_) => format!("goto l{};", target),
+            Instruction::JZero(_) => {
+                format!(
+                    "need({}, 1);\n    if (stack[--len] == 0) goto l{};",
+                    ip, target
+                )
+            }
+            Instruction::JNeg(_) => {
+                format!(
+                    "need({}, 1);\n    if (stack[--len] < 0) goto l{};",
+                    ip, target
+                )
+            }
+            Instruction::EndOfSubroutine => {
+                returns = true;
+                format!("returning({}); goto ret;", ip)
+            }
+            Instruction::EndOfProgram => "fflush(stdout);\n    return 0;".to_string(),
+            Instruction::PrintChar => format!("print_char({});", ip),
+            Instruction::PrintInt => format!("print_int({});", ip),
+            Instruction::ReadChar => format!("read_char({});", ip),
+            Instruction::ReadInt => format!("read_int({});", ip),
+        };
+        source += &format!("    {}\n", code);
+    }
+    source += &format!(
+        "    fail({}, \"reached the end of the program without an end instruction\");\n",
+        instructions.len()
+    );
+
+    // a subroutine returns to the call that comes before its place on the call stack
+    if returns {
+        source += "ret:\n    switch (calls[--calls_len]) {\n";
+        for call in 0..calls {
+            source += &format!("    case {}: goto r{};\n", call, call);
+        }
+        source += "    }\n";
+    }
+    source += "    return 0;\n}\n";
+    Ok(source)
+}
+
+// i32::MIN can not be written as a literal
+fn c_int(v: i32) -> String {
+    if v == i32::MIN {
+        "INT32_MIN".to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+// A string literal, with octal escapes for anything but printable ASCII
+fn c_string(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            // a ? could start a trigraph
+            b' '..=b'~' if byte != b'?' => literal.push(byte as char),
+            _ => literal += &format!("\\{:03o}", byte),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[test]
+fn c_strings_are_escaped() {
+    assert_eq!("\"JZero(\\\"a\\\\b\\\")\"", c_string("JZero(\"a\\b\")"));
+    assert_eq!("\"\\011\\303\\251\\077\"", c_string("\té?"));
+}
+
+#[test]
+fn branches_become_gotos() {
+    use crate::assembler::assemble;
+    use crate::linker::{LinkError, LinkErrorKind};
+
+    let program = assemble(
+        "    call f
+             end
+         f:  push 1
+             jz f
+             ret",
+    )
+    .unwrap();
+    let source = generate_c(&program, IntegerMode::Wrapping).unwrap();
+    assert!(source.contains("#define MODE WRAPPING\n"));
+    assert!(source.contains("    call(0); goto l2;\nr0:\n"));
+    assert!(source.contains("    /* f: */\nl2:\n    /* push 1 */\n    push(1);\n"));
+    assert!(source.contains("if (stack[--len] == 0) goto l2;"));
+    assert!(source.contains("    case 0: goto r0;\n"));
+
+    let program = assemble("jmp nowhere").unwrap();
+    assert_eq!(
+        Err(CompileError::Link(LinkError {
+            ip: 0,
+            kind: LinkErrorKind::UnknownLabel("nowhere".to_string())
+        })),
+        generate_c(&program, IntegerMode::Checked)
+    );
+    assert_eq!(
+        Err(CompileError::UnsupportedIntegerMode(IntegerMode::Promote)),
+        generate_c(&program, IntegerMode::Promote)
+    );
+}
@@ -1,15 +1,56 @@
 use std::path::PathBuf;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{self, prelude::*};
 use crate::instruction::*;
+use crate::arithmetic::{IntegerMode, Number};
+use crate::disassembler::disassemble_instruction;
+use crate::linker::{self, LinkError};
 
-pub fn generate_source_code(instructions: &Vec<Instruction>, path: &PathBuf) {
+/// Why a program can not be compiled to a standalone program or to C
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    Link(LinkError),
+    /// Compiled programs use 32 bits integers, which can not be promoted
+    UnsupportedIntegerMode(IntegerMode),
+}
+
+impl From<LinkError> for CompileError {
+    fn from(error: LinkError) -> CompileError {
+        CompileError::Link(error)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Link(e) => write!(f, "{}", e),
+            CompileError::UnsupportedIntegerMode(mode) => write!(
+                f,
+                "the {:?} integer mode is not supported by compiled programs, which use 32 bits \
+                 integers",
+                mode
+            ),
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+pub fn generate_source_code(instructions: &[Instruction], path: &PathBuf) {
     let display = path.display();
     let mut file = match File::create(path) {
         Err(why) => panic!("couldn't create {}: {:?}", display, why),
         Ok(file) => file,
     };
+    if let Err(why) = write_source_code(instructions, &mut file) {
+        panic!("couldn't write {}: {:?}", display, why);
+    }
+}
 
+/// Writes a rust program that runs the instructions with the virtual machine
+pub fn write_source_code<W: Write>(instructions: &[Instruction], file: &mut W) -> io::Result<()> {
     let beginning = String::from("extern crate whitespace;
 
 use whitespace::parser::*;
@@ -26,50 +67,512 @@ fn main() -> std::io::Result<()> {
 
     let mut vm = VirtualMachine::new(&instructions);
 
-    vm.run_program();
+    if let Err(e) = vm.run_program() {
+        eprintln!(\"{}\", e);
+    }
     Ok(())
 }
 ");
-    let _ = file.write(&beginning.as_bytes());
+    file.write_all(beginning.as_bytes())?;
     for instruction in instructions.iter() {
-        let _ = file.write("\t\t".as_bytes());
-        let _ = write_as_rust_string(&instruction, &mut file);
-        let _ = file.write(",\n".as_bytes());
+        file.write_all("\t\t".as_bytes())?;
+        write_as_rust_string(instruction, file)?;
+        file.write_all(",\n".as_bytes())?;
     }
+    file.write_all(end.as_bytes())
+}
 
-    println!("{:?}", instructions);
+/// Writes the rust expression that builds the instruction
+pub fn write_as_rust_string<W: Write>(instruction: &Instruction, file: &mut W) -> io::Result<()> {
+    let expression = match instruction {
+        Instruction::Push(v) => format!("Push({})", v),
+        Instruction::PushBig(v) => format!("PushBig(\"{}\".parse().unwrap())", v),
+        Instruction::Duplicate => "Duplicate".to_string(),
+        Instruction::CopyNth(v) => format!("CopyNth({})", v),
+        Instruction::Swap => "Swap".to_string(),
+        Instruction::Discard => "Discard".to_string(),
+        Instruction::Slide(v) => format!("Slide({})", v),
+        Instruction::Add => "Add".to_string(),
+        Instruction::Sub => "Sub".to_string(),
+        Instruction::Mul => "Mul".to_string(),
+        Instruction::Div => "Div".to_string(),
+        Instruction::Mod => "Mod".to_string(),
+        Instruction::Store => "Store".to_string(),
+        Instruction::Retrieve => "Retrieve".to_string(),
+        Instruction::SetLabel(label) => format!("SetLabel({:?}.to_string())", label),
+        Instruction::CallSubroutine(subroutine) => {
+            format!("CallSubroutine({:?}.to_string())", subroutine)
+        }
+        Instruction::Jump(label) => format!("Jump({:?}.to_string())", label),
+        Instruction::JZero(label) => format!("JZero({:?}.to_string())", label),
+        Instruction::JNeg(label) => format!("JNeg({:?}.to_string())", label),
+        Instruction::EndOfSubroutine => "EndOfSubroutine".to_string(),
+        Instruction::EndOfProgram => "EndOfProgram".to_string(),
+        Instruction::PrintChar => "PrintChar".to_string(),
+        Instruction::PrintInt => "PrintInt".to_string(),
+        Instruction::ReadChar => "ReadChar".to_string(),
+        Instruction::ReadInt => "ReadInt".to_string(),
+    };
+    file.write_all(expression.as_bytes())
+}
 
-    let _ = file.write(&end.as_bytes());
+// The part of the standalone programs that does not depend on the instructions. It behaves
+// like the virtual machine with 32 bits integers.
+const STANDALONE_RUNTIME: &str = r#"
+struct Machine {
+    stack: Vec<i32>,
+    heap: HashMap<i32, i32>,
+    calls: Vec<usize>,
+    input: io::BufReader<io::Stdin>,
+    output: io::BufWriter<io::Stdout>,
 }
 
+impl Machine {
+    fn new() -> Machine {
+        Machine {
+            stack: Vec::with_capacity(64),
+            heap: HashMap::new(),
+            calls: Vec::new(),
+            input: io::BufReader::new(io::stdin()),
+            output: io::BufWriter::new(io::stdout()),
+        }
+    }
+
+    // Reports an error like the virtual machine does, then exits with 3
+    fn fail(&mut self, ip: usize, message: String) -> ! {
+        let _ = self.output.flush();
+        match INSTRUCTIONS.get(ip) {
+            Some(instruction) => eprintln!("{} (instruction {}: {})", message, ip, instruction),
+            None => eprintln!("{} (instruction {})", message, ip),
+        }
+        process::exit(3)
+    }
 
-pub fn write_as_rust_string(instruction: &Instruction, file: &mut File) {
-    let _ = match instruction {
-        Instruction::Push(v) => file.write(format!("Push({})", v).as_bytes()),
-        Instruction::Duplicate => file.write("Duplicate".as_bytes()),
-        Instruction::CopyNth(v) => file.write(format!("Push({})", v).as_bytes()),
-        Instruction::Swap => file.write("Swap".as_bytes()),
-        Instruction::Discard => file.write("Discard".as_bytes()),
-        Instruction::Slide(v) => file.write(format!("Slide({})", v).as_bytes()),
-        Instruction::Add => file.write("Add".as_bytes()),
-        Instruction::Sub => file.write("Sub".as_bytes()),
-        Instruction::Mul => file.write("Mul".as_bytes()),
-        Instruction::Div => file.write("Div".as_bytes()),
-        Instruction::Mod => file.write("Mod".as_bytes()),
-        Instruction::Store => file.write("Store".as_bytes()),
-        Instruction::Retrieve => file.write("Retrieve".as_bytes()),
-        Instruction::SetLabel(label) => file.write(format!("SetLabel(\"{}\".to_string())", label).as_bytes()),
-        Instruction::CallSubroutine(subroutine) => 
-            file.write(format!("CallSubroutine(\"{}\".to_string())", subroutine).as_bytes()),
-        
-        Instruction::Jump(label) => file.write(format!("Jump(\"{}\".to_string())", label).as_bytes()),
-        Instruction::JZero(label) => file.write(format!("JZero(\"{}\".to_string())", label).as_bytes()),
-        Instruction::JNeg(label) => file.write(format!("JNeg(\"{}\".to_string())", label).as_bytes()),
-        Instruction::EndOfSubroutine => file.write("EndOfSubroutine".as_bytes()),
-        Instruction::EndOfProgram => file.write("EndOfProgram".as_bytes()),
-        Instruction::PrintChar => file.write("PrintChar".as_bytes()),
-        Instruction::PrintInt => file.write("PrintInt".as_bytes()),
-        Instruction::ReadChar => file.write("ReadChar".as_bytes()),
-        Instruction::ReadInt => file.write("ReadInt".as_bytes()),
+    fn need(&mut self, ip: usize, n: usize) {
+        if self.stack.len() < n {
+            let available = self.stack.len();
+            self.fail(
+                ip,
+                format!("not enough elements on the stack: {} needed, {} available", n, available),
+            );
+        }
+    }
+
+    fn pop(&mut self, ip: usize) -> i32 {
+        self.need(ip, 1);
+        self.stack.pop().unwrap()
+    }
+
+    // The result of an operation, according to the integer mode
+    fn integer(&mut self, ip: usize, v: i64) -> i32 {
+        if v >= i64::from(i32::MIN) && v <= i64::from(i32::MAX) {
+            return v as i32;
+        }
+        match MODE {
+            Mode::Checked => self.fail(ip, "arithmetic overflow".to_string()),
+            Mode::Wrapping => v as i32,
+            Mode::Saturating if v < 0 => i32::MIN,
+            Mode::Saturating => i32::MAX,
+        }
+    }
+
+    // Rounds towards negative infinity
+    fn div(&mut self, ip: usize, l: i32, r: i32) -> i32 {
+        if r == 0 {
+            self.fail(ip, "division by zero".to_string());
+        }
+        if r == -1 {
+            return self.integer(ip, -i64::from(l));
+        }
+        if l % r != 0 && (l < 0) != (r < 0) {
+            l / r - 1
+        } else {
+            l / r
+        }
+    }
+
+    // The result has the sign of the divisor
+    fn modulo(&mut self, ip: usize, l: i32, r: i32) -> i32 {
+        if r == 0 {
+            self.fail(ip, "division by zero".to_string());
+        }
+        let remainder = l.wrapping_rem(r);
+        if remainder != 0 && (remainder < 0) != (r < 0) {
+            remainder + r
+        } else {
+            remainder
+        }
+    }
+
+    fn store(&mut self, ip: usize, address: i32, value: i32) {
+        if address < 0 {
+            self.fail(ip, format!("invalid heap address {}", address));
+        }
+        self.heap.insert(address, value);
+    }
+
+    fn retrieve(&mut self, ip: usize, address: i32) -> i32 {
+        if address < 0 {
+            self.fail(ip, format!("invalid heap address {}", address));
+        }
+        self.heap.get(&address).cloned().unwrap_or(0)
+    }
+
+    fn write(&mut self, ip: usize, text: &str) {
+        if let Err(e) = self.output.write_all(text.as_bytes()) {
+            self.fail(ip, format!("unable to write to the output: {}", e));
+        }
+    }
+
+    fn print_char(&mut self, ip: usize) {
+        let code = self.pop(ip);
+        match std::char::from_u32(code as u32).filter(|_| code >= 0) {
+            Some(c) => self.write(ip, &c.to_string()),
+            None => self.fail(ip, format!("{} is not a valid character", code)),
+        }
+    }
+
+    fn print_int(&mut self, ip: usize) {
+        let v = self.pop(ip);
+        self.write(ip, &v.to_string());
+    }
+
+    fn read_byte(&mut self, ip: usize) -> Option<u8> {
+        let byte = match self.input.fill_buf() {
+            Ok(buffer) => buffer.first().cloned(),
+            Err(_) => self.fail(ip, "unable to read the input".to_string()),
+        };
+        if byte.is_some() {
+            self.input.consume(1);
+        }
+        byte
+    }
+
+    // Reads a UTF-8 character
+    fn read_char(&mut self, ip: usize) {
+        self.need(ip, 1);
+        let _ = self.output.flush();
+        let first = match self.read_byte(ip) {
+            Some(b) => b,
+            None => self.fail(ip, "end of input".to_string()),
+        };
+        let width = match first {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => self.fail(ip, "input is not valid UTF-8".to_string()),
+        };
+        let mut bytes = vec![first];
+        for _ in 1..width {
+            match self.read_byte(ip) {
+                Some(b) => bytes.push(b),
+                None => self.fail(ip, "input is not valid UTF-8".to_string()),
+            }
+        }
+        let c = match std::str::from_utf8(&bytes) {
+            Ok(s) => s.chars().next().unwrap() as i32,
+            Err(_) => self.fail(ip, "input is not valid UTF-8".to_string()),
+        };
+        let address = self.pop(ip);
+        self.store(ip, address, c);
+    }
+
+    fn read_int(&mut self, ip: usize) {
+        self.need(ip, 1);
+        let _ = self.output.flush();
+        let mut input = String::new();
+        match self.input.read_line(&mut input) {
+            Ok(0) => self.fail(ip, "end of input".to_string()),
+            Ok(_) => {}
+            Err(_) => self.fail(ip, "unable to read the input".to_string()),
+        }
+        let line = input.trim_end_matches(|c| c == '\n' || c == '\r');
+        let v = match parse_integer(line) {
+            Some(v) => v,
+            None => self.fail(ip, format!("{:?} is not a valid integer", line)),
+        };
+        let address = self.pop(ip);
+        self.store(ip, address, v);
+    }
+}
+
+// Parses a decimal, 0x or 0o number, with an optional sign, like the virtual machine
+fn parse_integer(text: &str) -> Option<i32> {
+    let text = text.trim();
+    let (negative, unsigned) = match text.chars().next() {
+        Some('-') => (true, &text[1..]),
+        Some('+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let (radix, digits) = if unsigned.starts_with("0x") || unsigned.starts_with("0X") {
+        (16, &unsigned[2..])
+    } else if unsigned.starts_with("0o") || unsigned.starts_with("0O") {
+        (8, &unsigned[2..])
+    } else {
+        (10, unsigned)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut magnitude: u64 = 0;
+    let mut wrapped: u32 = 0;
+    for c in digits.chars() {
+        let digit = c.to_digit(radix)?;
+        magnitude = magnitude
+            .saturating_mul(u64::from(radix))
+            .saturating_add(u64::from(digit));
+        wrapped = wrapped.wrapping_mul(radix).wrapping_add(digit);
+    }
+    let value = if negative {
+        -i128::from(magnitude)
+    } else {
+        i128::from(magnitude)
+    };
+    if value >= i128::from(i32::MIN) && value <= i128::from(i32::MAX) {
+        return Some(value as i32);
+    }
+    match MODE {
+        Mode::Checked => None,
+        Mode::Wrapping if negative => Some(wrapped.wrapping_neg() as i32),
+        Mode::Wrapping => Some(wrapped as i32),
+        Mode::Saturating if negative => Some(i32::MIN),
+        Mode::Saturating => Some(i32::MAX),
+    }
+}
+"#;
+
+/// Writes a rust program that runs the instructions without the virtual machine, nor the
+/// whitespace crate. The program is split in basic blocks, which are the arms of a
+/// `loop { match block { ... } }`, every block being named after the index of its first
+/// instruction. It behaves like the virtual machine with 32 bits integers, and exits with 3
+/// after a runtime error.
+pub fn generate_standalone_source_code(
+    instructions: &[Instruction],
+    mode: IntegerMode,
+) -> Result<String, CompileError> {
+    if mode == IntegerMode::Promote {
+        return Err(CompileError::UnsupportedIntegerMode(mode));
+    }
+    let targets = linker::link(instructions)?;
+    let len = instructions.len();
+
+    // a block starts at the beginning, at every branch target and after every branch
+    let mut leaders = vec![false; len + 1];
+    leaders[0] = true;
+    leaders[len] = true;
+    for (ip, instruction) in instructions.iter().enumerate() {
+        if let Some(target) = targets[ip] {
+            leaders[target] = true;
+        }
+        if branches(instruction) {
+            leaders[ip + 1] = true;
+        }
+    }
+
+    let mut source = String::from(
+        "// Generated from a whitespace program\n\
+         #![allow(dead_code, unreachable_code, unused_mut)]\n\
+         use std::collections::HashMap;\nuse std::io::{self, BufRead, Write};\nuse std::process;\n\n\
+         #[derive(PartialEq)]\nenum Mode {\n    Checked,\n    Wrapping,\n    Saturating,\n}\n\n",
+    );
+    source += &format!("const MODE: Mode = Mode::{:?};\n\n", mode);
+    // how instructions are shown in error messages
+    source += "const INSTRUCTIONS: &[&str] = &[\n";
+    for instruction in instructions.iter() {
+        source += &format!("    {:?},\n", format!("{:?}", instruction));
+    }
+    source += "];\n";
+    source += STANDALONE_RUNTIME;
+
+    source += "\nfn main() {\n    let mut m = Machine::new();\n";
+    if len == 0 {
+        source += "    let _ = m.output.flush();\n}\n";
+        return Ok(source);
+    }
+    source += "    let mut block = 0;\n    loop {\n        match block {\n";
+    let mut start = 0;
+    for end in 1..=len {
+        if !leaders[end] {
+            continue;
+        }
+        source += &format!("            {} => {{\n", start);
+        for ip in start..end {
+            let assembly = disassemble_instruction(&instructions[ip]);
+            source += &format!("                // {}\n", assembly.trim());
+            for line in standalone_code(&instructions[ip], ip, targets[ip], mode).lines() {
+                source += &format!("                {}\n", line);
+            }
+        }
+        if !branches(&instructions[end - 1]) {
+            source += &format!("                block = {};\n", end);
+        }
+        source += "            }\n";
+        start = end;
+    }
+    source += &format!(
+        "            {} => m.fail({}, \"{}\".into()),\n",
+        len, len, "reached the end of the program without an end instruction"
+    );
+    source += "            _ => unreachable!(),\n        }\n    }\n}\n";
+    Ok(source)
+}
+
+// Whether the instruction chooses the block that runs next, which then starts after it
+fn branches(instruction: &Instruction) -> bool {
+    linker::branch_label(instruction).is_some()
+        || matches!(
+            instruction,
+            Instruction::EndOfSubroutine | Instruction::EndOfProgram
+        )
+}
+
+// The code of an instruction, in the block of `m`
+fn standalone_code(
+    instruction: &Instruction,
+    ip: usize,
+    target: Option<usize>,
+    mode: IntegerMode,
+) -> String {
+    let target = target.unwrap_or_default();
+    let binary = |operation: &str| {
+        format!(
+            "m.need({}, 2);\n\
+             let r = m.stack.pop().unwrap();\n\
+             let l = m.stack.pop().unwrap();\n\
+             let v = {};\n\
+             m.stack.push(v);",
+            ip, operation
+        )
     };
-}
\ No newline at end of file
+    match instruction {
+        Instruction::Push(v) => format!("m.stack.push({});", v),
+        Instruction::PushBig(v) => match i32::from_bignum(v, mode) {
+            Ok(v) => format!("m.stack.push({});", v),
+            Err(_) => format!("m.fail({}, \"arithmetic overflow\".to_string());", ip),
+        },
+        Instruction::Duplicate => format!(
+            "m.need({}, 1);\nlet v = m.stack[m.stack.len() - 1];\nm.stack.push(v);",
+            ip
+        ),
+        Instruction::CopyNth(v) | Instruction::Slide(v) if *v < 0 => {
+            format!("m.fail({}, \"negative argument {}\".to_string());", ip, v)
+        }
+        Instruction::CopyNth(v) => format!(
+            "m.need({}, {});\nlet v = m.stack[m.stack.len() - {}];\nm.stack.push(v);",
+            ip,
+            *v as usize + 1,
+            *v as usize + 1
+        ),
+        Instruction::Swap => format!(
+            "m.need({}, 2);\nlet len = m.stack.len();\nm.stack.swap(len - 1, len - 2);",
+            ip
+        ),
+        Instruction::Discard => format!("m.need({}, 1);\nm.stack.pop();", ip),
+        Instruction::Slide(v) => format!(
+            "m.need({}, {});\n\
+             let top = m.stack.pop().unwrap();\n\
+             let len = m.stack.len();\n\
+             m.stack.truncate(len - {});\n\
+             m.stack.push(top);",
+            ip,
+            *v as usize + 1,
+            v
+        ),
+        Instruction::Add => binary(&format!("m.integer({}, i64::from(l) + i64::from(r))", ip)),
+        Instruction::Sub => binary(&format!("m.integer({}, i64::from(l) - i64::from(r))", ip)),
+        Instruction::Mul => binary(&format!("m.integer({}, i64::from(l) * i64::from(r))", ip)),
+        Instruction::Div => binary(&format!("m.div({}, l, r)", ip)),
+        Instruction::Mod => binary(&format!("m.modulo({}, l, r)", ip)),
+        Instruction::Store => format!(
+            "m.need({}, 2);\n\
+             let value = m.stack.pop().unwrap();\n\
+             let address = m.stack.pop().unwrap();\n\
+             m.store({}, address, value);",
+            ip, ip
+        ),
+        Instruction::Retrieve => format!(
+            "let address = m.pop({});\nlet v = m.retrieve({}, address);\nm.stack.push(v);",
+            ip, ip
+        ),
+        Instruction::SetLabel(_) => String::new(),
+        Instruction::CallSubroutine(_) => {
+            format!("m.calls.push({});\nblock = {};", ip + 1, target)
+        }
+        Instruction::Jump(_) => format!("block = {};", target),
+        Instruction::JZero(_) => format!(
+            "block = if m.pop({}) == 0 {{ {} }} else {{ {} }};",
+            ip,
+            target,
+            ip + 1
+        ),
+        Instruction::JNeg(_) => format!(
+            "block = if m.pop({}) < 0 {{ {} }} else {{ {} }};",
+            ip,
+            target,
+            ip + 1
+        ),
+        Instruction::EndOfSubroutine => format!(
+            "block = match m.calls.pop() {{\n    \
+                 Some(next) => next,\n    \
+                 None => m.fail({}, \"{}\".into()),\n\
+             }};",
+            ip, "can't return from a subroutine: call stack is empty"
+        ),
+        Instruction::EndOfProgram => "let _ = m.output.flush();\nreturn;".to_string(),
+        Instruction::PrintChar => format!("m.print_char({});", ip),
+        Instruction::PrintInt => format!("m.print_int({});", ip),
+        Instruction::ReadChar => format!("m.read_char({});", ip),
+        Instruction::ReadInt => format!("m.read_int({});", ip),
+    }
+}
+
+#[test]
+fn standalone_programs_are_split_in_blocks() {
+    use crate::assembler::assemble;
+    use crate::linker::LinkErrorKind;
+
+    let program = assemble(
+        "    push 3
+         loop:
+             push 1
+             sub
+             dup
+             jz done
+             call f
+             jmp loop
+         f:  ret
+         done:
+             end",
+    )
+    .unwrap();
+    let source = generate_standalone_source_code(&program, IntegerMode::Saturating).unwrap();
+    assert!(source.contains("const MODE: Mode = Mode::Saturating;\n"));
+    assert!(source.contains("const INSTRUCTIONS: &[&str] = &[\n    \"Push(3)\",\n"));
+    // blocks start at labels and after branches
+    let blocks: Vec<&str> = source
+        .lines()
+        .filter(|line| line.starts_with("            ") && line.trim_end().ends_with("=> {"))
+        .map(|line| line.trim())
+        .collect();
+    assert_eq!(
+        vec!["0 => {", "1 => {", "6 => {", "7 => {", "8 => {", "10 => {"],
+        blocks
+    );
+    assert!(source.contains("                block = if m.pop(5) == 0 { 10 } else { 6 };\n"));
+    assert!(source.contains("                m.calls.push(7);\n                block = 8;\n"));
+    assert!(source.contains("            12 => m.fail(12, "));
+
+    let program = assemble("jmp nowhere").unwrap();
+    assert_eq!(
+        Err(CompileError::Link(LinkError {
+            ip: 0,
+            kind: LinkErrorKind::UnknownLabel("nowhere".to_string())
+        })),
+        generate_standalone_source_code(&program, IntegerMode::Checked)
+    );
+    assert_eq!(
+        Err(CompileError::UnsupportedIntegerMode(IntegerMode::Promote)),
+        generate_standalone_source_code(&[], IntegerMode::Promote)
+    );
+}
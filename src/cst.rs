@@ -0,0 +1,136 @@
+use crate::dialect::Dialect;
+use crate::instruction::Instruction;
+use crate::parser::{parse_program_spanned, ParseError, ParseOptions, Span};
+use std::fmt;
+
+/// A lossless view of a program: every character of the source, comments included,
+/// belongs to exactly one node or to the trailing text. Printing it gives the source back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst {
+    pub nodes: Vec<Node>,
+    /// What comes after the last instruction
+    pub trailing: String,
+    /// The dialect the source is written in, which tells tokens from comments
+    pub dialect: Dialect,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    /// The comments between the previous instruction and this one
+    pub leading: String,
+    /// The source of the instruction, including comment characters written in the middle of it
+    pub text: String,
+    pub instruction: Instruction,
+    pub span: Span,
+}
+
+impl Node {
+    /// The commentary attached to the instruction, without the tokens of the dialect of the
+    /// tree it belongs to
+    pub fn comment(&self, dialect: &Dialect) -> String {
+        let mut comment = String::new();
+        let mut chars = self.leading.chars().chain(self.text.chars()).peekable();
+        while let Some(c) = chars.next() {
+            if *dialect == Dialect::Crlf && c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            } else if dialect.token(c).is_none() {
+                comment.push(c);
+            }
+        }
+        comment
+    }
+}
+
+impl Cst {
+    pub fn parse(source: &str, options: &ParseOptions) -> Result<Cst, ParseError> {
+        let program = parse_program_spanned(source, options)?;
+        let mut nodes = Vec::with_capacity(program.len());
+        let mut previous_end = 0;
+        for spanned in program {
+            let span = spanned.span;
+            nodes.push(Node {
+                leading: source[previous_end..span.start].to_string(),
+                text: source[span.start..span.end].to_string(),
+                instruction: spanned.instruction,
+                span,
+            });
+            previous_end = span.end;
+        }
+        Ok(Cst {
+            nodes,
+            trailing: source[previous_end..].to_string(),
+            dialect: options.dialect.clone(),
+        })
+    }
+
+    pub fn instructions(&self) -> Vec<Instruction> {
+        self.nodes.iter().map(|n| n.instruction.clone()).collect()
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in self.nodes.iter() {
+            write!(f, "{}{}", node.leading, node.text)?;
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+#[test]
+fn cst_round_trips_every_example() {
+    let options = ParseOptions {
+        allow_sign_only_numbers: true,
+        ..ParseOptions::default()
+    };
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|e| e == "ws") != Some(true) {
+            continue;
+        }
+        let source = std::fs::read_to_string(&path).unwrap();
+        let cst = Cst::parse(&source, &options).unwrap();
+        assert_eq!(source, cst.to_string(), "{:?}", path);
+        assert_eq!(
+            crate::parser::parse_program_with(&source, &options).unwrap(),
+            cst.instructions()
+        );
+    }
+}
+
+#[test]
+fn comments_are_attached_to_instructions() {
+    let cst = Cst::parse(
+        "push1:   \tone\nadd:\t   done\n\n\nbye",
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(3, cst.nodes.len());
+    assert_eq!("push1:", cst.nodes[0].leading);
+    assert_eq!("   \tone\n", cst.nodes[0].text);
+    assert_eq!("push1:one", cst.nodes[0].comment(&cst.dialect));
+    assert_eq!(Instruction::Add, cst.nodes[1].instruction);
+    assert_eq!("add:", cst.nodes[1].comment(&cst.dialect));
+    assert_eq!("done", cst.nodes[2].leading);
+    assert_eq!("bye", cst.trailing);
+}
+
+#[test]
+fn comments_depend_on_the_dialect() {
+    let stl = ParseOptions {
+        dialect: Dialect::Stl,
+        ..ParseOptions::default()
+    };
+    let cst = Cst::parse("push 1: SSSTL add: TSSS", &stl).unwrap();
+    assert_eq!("push 1: ", cst.nodes[0].comment(&cst.dialect));
+    assert_eq!(" add: ", cst.nodes[1].comment(&cst.dialect));
+
+    let crlf = ParseOptions {
+        dialect: Dialect::Crlf,
+        ..ParseOptions::default()
+    };
+    let cst = Cst::parse("push\r1   \t\r\nadd\t   ", &crlf).unwrap();
+    assert_eq!("push\r1", cst.nodes[0].comment(&cst.dialect));
+    assert_eq!("add", cst.nodes[1].comment(&cst.dialect));
+    assert_eq!("push\r1   \t\r\nadd\t   ", cst.to_string());
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How the three tokens of the language (space, tab and line feed) are written in a source
+/// file. Whatever is not a token is a comment.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Dialect {
+    /// Space, tab and line feed
+    #[default]
+    Standard,
+    /// Like `Standard`, with a "\r\n" read as a single line feed
+    Crlf,
+    /// The visible letters S (space), T (tab) and L (line feed)
+    Stl,
+    /// The letters A (space), B (tab) and C (line feed) of the reference implementation
+    Abc,
+    /// Three different characters, for space, tab and line feed
+    Custom {
+        space: char,
+        tab: char,
+        line_feed: char,
+    },
+}
+
+impl Dialect {
+    // The characters used for space, tab and line feed
+    fn symbols(&self) -> (char, char, char) {
+        match self {
+            Dialect::Standard | Dialect::Crlf => (' ', '\t', '\n'),
+            Dialect::Stl => ('S', 'T', 'L'),
+            Dialect::Abc => ('A', 'B', 'C'),
+            Dialect::Custom {
+                space,
+                tab,
+                line_feed,
+            } => (*space, *tab, *line_feed),
+        }
+    }
+
+    /// The standard token written as `c` in this dialect, if any
+    pub fn token(&self, c: char) -> Option<char> {
+        let (space, tab, line_feed) = self.symbols();
+        if c == space {
+            Some(' ')
+        } else if c == tab {
+            Some('\t')
+        } else if c == line_feed {
+            Some('\n')
+        } else {
+            None
+        }
+    }
+
+    /// The tokens of a source, as standard whitespace, with the byte range of each of them
+    pub fn scan(&self, source: &str) -> Vec<(char, usize, usize)> {
+        let mut tokens = Vec::new();
+        let mut chars = source.char_indices().peekable();
+        while let Some((offset, c)) = chars.next() {
+            if *self == Dialect::Crlf && c == '\r' {
+                if let Some((_, '\n')) = chars.peek() {
+                    chars.next();
+                    tokens.push(('\n', offset, offset + 2));
+                }
+            } else if let Some(token) = self.token(c) {
+                tokens.push((token, offset, offset + c.len_utf8()));
+            }
+        }
+        tokens
+    }
+
+    /// Writes a standard program in this dialect. Comments are copied as they are.
+    pub fn encode(&self, ws_program: &str) -> String {
+        let (space, tab, line_feed) = self.symbols();
+        let mut encoded = String::with_capacity(ws_program.len());
+        for c in ws_program.chars() {
+            match c {
+                ' ' => encoded.push(space),
+                '\t' => encoded.push(tab),
+                '\n' if *self == Dialect::Crlf => encoded.push_str("\r\n"),
+                '\n' => encoded.push(line_feed),
+                c => encoded.push(c),
+            }
+        }
+        encoded
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dialect::Standard => write!(f, "standard"),
+            Dialect::Crlf => write!(f, "crlf"),
+            Dialect::Stl => write!(f, "stl"),
+            Dialect::Abc => write!(f, "abc"),
+            Dialect::Custom {
+                space,
+                tab,
+                line_feed,
+            } => write!(f, "custom:{}{}{}", space, tab, line_feed),
+        }
+    }
+}
+
+// The names printed by Display: standard, crlf, stl, abc or custom: followed by the symbols
+// for space, tab and line feed, like custom:.-/
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Dialect, String> {
+        match s {
+            "standard" => Ok(Dialect::Standard),
+            "crlf" => Ok(Dialect::Crlf),
+            "stl" => Ok(Dialect::Stl),
+            "abc" => Ok(Dialect::Abc),
+            _ if s.starts_with("custom:") => {
+                let symbols: Vec<char> = s["custom:".len()..].chars().collect();
+                match symbols[..] {
+                    [space, tab, line_feed]
+                        if space != tab && tab != line_feed && space != line_feed =>
+                    {
+                        Ok(Dialect::Custom {
+                            space,
+                            tab,
+                            line_feed,
+                        })
+                    }
+                    _ => Err(format!(
+                        "{} should be custom: followed by three different characters",
+                        s
+                    )),
+                }
+            }
+            _ => Err(format!("unknown dialect {}", s)),
+        }
+    }
+}
+
+#[test]
+fn dialects_scan_and_encode() {
+    let program = "   \t\n\t\n \t\n\n\n";
+    for dialect in ["standard", "crlf", "stl", "abc", "custom:.-/"].iter() {
+        let dialect: Dialect = dialect.parse().unwrap();
+        assert_eq!(dialect.to_string().parse(), Ok(dialect.clone()));
+        let encoded = dialect.encode(program);
+        let tokens: String = dialect.scan(&encoded).iter().map(|t| t.0).collect();
+        assert_eq!(program, tokens, "{}", dialect);
+    }
+
+    assert_eq!("SSSTLTLSTLLL", Dialect::Stl.encode(program));
+    assert_eq!(
+        vec![(' ', 1, 2), ('\n', 2, 4), ('\t', 6, 7)],
+        Dialect::Crlf.scan("x \r\n\ry\t")
+    );
+    assert_eq!(vec![('\t', 0, 1)], Dialect::Abc.scan("Bad"));
+    assert!("custom:..-".parse::<Dialect>().is_err());
+    assert!("ebcdic".parse::<Dialect>().is_err());
+}
@@ -0,0 +1,121 @@
+use crate::assembler::{generated_label, is_word_char, MNEMONICS};
+use crate::instruction::{label_bits, Instruction};
+
+/// Writes instructions in the assembly language read by the `assembler` module, one per
+/// line. Assembling the result gives the same instructions back.
+pub fn disassemble(instructions: &[Instruction]) -> String {
+    let mut assembly = String::new();
+    for instruction in instructions.iter() {
+        assembly += &disassemble_instruction(instruction);
+        assembly.push('\n');
+    }
+    assembly
+}
+
+/// A line of assembly: `name:` for a label, an indented mnemonic for anything else
+pub fn disassemble_instruction(instruction: &Instruction) -> String {
+    if let Instruction::SetLabel(label) = instruction {
+        return format!("{}:", label_name(label));
+    }
+
+    let mnemonic = MNEMONICS
+        .iter()
+        .find(|(_, form)| form.creates(instruction))
+        .map(|(mnemonic, _)| *mnemonic)
+        .expect("every instruction has a mnemonic");
+    match instruction {
+        Instruction::Push(v) | Instruction::CopyNth(v) | Instruction::Slide(v) => {
+            format!("    {} {}", mnemonic, v)
+        }
+        Instruction::PushBig(v) => format!("    {} {}", mnemonic, v),
+        Instruction::CallSubroutine(label)
+        | Instruction::Jump(label)
+        | Instruction::JZero(label)
+        | Instruction::JNeg(label) => format!("    {} {}", mnemonic, label_name(label)),
+        _ => format!("    {}", mnemonic),
+    }
+}
+
+/// How a label is written in assembly: as it is when it is a valid name, between double
+/// quotes when it is printable, and as a generated name like `_0a05` otherwise. Labels
+/// that are not whole bytes end their generated name with the remaining bits, as in `_41.01`.
+pub fn label_name(label: &str) -> String {
+    let is_name = !label.is_empty()
+        && label.chars().all(is_word_char)
+        && !label.starts_with(|c: char| c.is_ascii_digit())
+        && generated_label(label).is_none();
+    if is_name {
+        label.to_string()
+    } else if label.chars().all(|c| c <= '\u{ff}' && !c.is_control()) {
+        format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        let bits = label_bits(label);
+        let (bytes, tail) = bits.split_at(bits.len() - bits.len() % 8);
+        let mut name = String::from("_");
+        for byte in bytes.chunks(8) {
+            let value = byte.iter().fold(0, |v, &bit| v << 1 | bit as u8);
+            name += &format!("{:02x}", value);
+        }
+        if !tail.is_empty() {
+            name.push('.');
+            name.extend(tail.iter().map(|&bit| if bit { '1' } else { '0' }));
+        }
+        name
+    }
+}
+
+#[test]
+fn label_names_are_readable() {
+    assert_eq!("loop", label_name("loop"));
+    assert_eq!("\"print string\"", label_name("print string"));
+    assert_eq!("\"1st\"", label_name("1st"));
+    assert_eq!("\"_ab\"", label_name("_ab"));
+    assert_eq!("\"a\\\"b\\\\\"", label_name("a\"b\\"));
+    assert_eq!("_0a05", label_name("\n\u{5}"));
+    assert_eq!("_7f41", label_name("\u{7f}A"));
+    assert_eq!("_.1", label_name("\u{103}"));
+    assert_eq!("_.01", label_name("\u{105}"));
+    assert_eq!("_03.1", label_name("\u{3}\u{103}"));
+}
+
+#[test]
+fn disassembled_programs_assemble_back() {
+    use crate::assembler::assemble;
+    use crate::parser::{parse_program_with, ParseOptions};
+    use Instruction::*;
+
+    let options = ParseOptions {
+        allow_sign_only_numbers: true,
+        ..ParseOptions::default()
+    };
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|e| e == "ws") != Some(true) {
+            continue;
+        }
+        let source = std::fs::read_to_string(&path).unwrap();
+        let program = parse_program_with(&source, &options).unwrap();
+        let assembly = disassemble(&program);
+        assert_eq!(Ok(program), assemble(&assembly), "{:?}", path);
+    }
+
+    let program = vec![
+        SetLabel("\u{1}\u{0}".to_string()),
+        Push(-3),
+        PushBig("-18446744073709551616".parse().unwrap()),
+        CopyNth(1),
+        Slide(2),
+        CallSubroutine("a b".to_string()),
+        JNeg("_00".to_string()),
+        Jump("\u{103}".to_string()),
+        JZero("\u{105}".to_string()),
+        EndOfProgram,
+    ];
+    let assembly = disassemble(&program);
+    assert_eq!(
+        "_0100:\n    push -3\n    push -18446744073709551616\n    copy 1\n    slide 2\n    \
+         call \"a b\"\n    jn \"_00\"\n    jmp _.1\n    jz _.01\n    end\n",
+        assembly
+    );
+    assert_eq!(Ok(program), assemble(&assembly));
+}
@@ -0,0 +1,189 @@
+use crate::bignum::BigNum;
+use crate::dialect::Dialect;
+use crate::disassembler::disassemble_instruction;
+use crate::instruction::{label_bits, Instruction};
+use crate::parser::INSTRUCTIONS;
+
+/// Settings that change how a program is written
+#[derive(Debug, Clone, Default)]
+pub struct EncodeOptions {
+    /// How space, tab and line feed are written
+    pub dialect: Dialect,
+    /// Write the assembly of every instruction as a comment before it
+    pub comments: bool,
+}
+
+/// Writes instructions as a whitespace program, without any comment
+pub fn encode_program(instructions: &[Instruction]) -> String {
+    instructions.iter().map(encode_instruction).collect()
+}
+
+pub fn encode_program_with(instructions: &[Instruction], options: &EncodeOptions) -> String {
+    let mut encoded = String::new();
+    for instruction in instructions.iter() {
+        if options.comments {
+            encoded += &comment(instruction, &options.dialect);
+        }
+        encoded += &options.dialect.encode(&encode_instruction(instruction));
+    }
+    encoded
+}
+
+// The assembly of an instruction, with a · instead of the characters read as tokens
+fn comment(instruction: &Instruction, dialect: &Dialect) -> String {
+    disassemble_instruction(instruction)
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || dialect.token(c).is_some() {
+                '·'
+            } else {
+                c
+            }
+        })
+        .filter(|c| dialect.token(*c).is_none())
+        .collect()
+}
+
+pub fn encode_instruction(instruction: &Instruction) -> String {
+    let prefix = INSTRUCTIONS
+        .iter()
+        .find(|(_, form)| form.creates(instruction))
+        .map(|(prefix, _)| *prefix)
+        .expect("every instruction has a prefix");
+    let argument = match instruction {
+        Instruction::Push(v) | Instruction::CopyNth(v) | Instruction::Slide(v) => {
+            encode_number(&BigNum::from(*v))
+        }
+        Instruction::PushBig(v) => encode_number(v),
+        Instruction::SetLabel(label)
+        | Instruction::CallSubroutine(label)
+        | Instruction::Jump(label)
+        | Instruction::JZero(label)
+        | Instruction::JNeg(label) => encode_label(label),
+        _ => String::new(),
+    };
+    prefix.to_string() + &argument
+}
+
+/// A sign, the binary digits of the absolute value and a line feed. Zero is written with a
+/// single digit, since a sign alone is not a valid number.
+pub fn encode_number(value: &BigNum) -> String {
+    let two = BigNum::from(2);
+    let mut digits = Vec::new();
+    let mut rest = value.abs();
+    while !rest.is_zero() {
+        let digit = rest.modulo(&two).unwrap();
+        digits.push(if digit.is_zero() { ' ' } else { '\t' });
+        rest = rest.div(&two).unwrap();
+    }
+    if digits.is_empty() {
+        digits.push(' ');
+    }
+
+    let mut encoded = String::with_capacity(digits.len() + 2);
+    encoded.push(if value.is_negative() { '\t' } else { ' ' });
+    encoded.extend(digits.iter().rev());
+    encoded.push('\n');
+    encoded
+}
+
+/// The bits of the label, as given by `label_bits`, then a line feed
+pub fn encode_label(label: &str) -> String {
+    let mut encoded: String = label_bits(label)
+        .into_iter()
+        .map(|bit| if bit { '\t' } else { ' ' })
+        .collect();
+    encoded.push('\n');
+    encoded
+}
+
+#[test]
+fn encode_number_works() {
+    assert_eq!("  \n", encode_number(&BigNum::zero()));
+    assert_eq!(" \t\n", encode_number(&BigNum::from(1)));
+    assert_eq!("\t\t \t\n", encode_number(&BigNum::from(-5)));
+    assert_eq!(
+        format!(" \t{}\n", " ".repeat(64)),
+        encode_number(&"18446744073709551616".parse().unwrap())
+    );
+}
+
+#[test]
+fn encode_label_keeps_every_bit() {
+    use crate::parser::match_string;
+
+    assert_eq!(" \t     \t\n", encode_label("A"));
+    for ws in ["\t\n", " \t\n", "\t\t \t\t\n", "      \t\t\t\n"].iter() {
+        let (_, label) = match_string(ws).unwrap();
+        assert_eq!(*ws, encode_label(&label));
+    }
+}
+
+#[test]
+fn encoded_examples_parse_back() {
+    use crate::parser::{parse_program_with, ParseOptions};
+
+    let options = ParseOptions {
+        allow_sign_only_numbers: true,
+        ..ParseOptions::default()
+    };
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map(|e| e == "ws") != Some(true) {
+            continue;
+        }
+        let source = std::fs::read_to_string(&path).unwrap();
+        let program = parse_program_with(&source, &options).unwrap();
+        let encoded = encode_program(&program);
+        assert_eq!(
+            Ok(program),
+            parse_program_with(&encoded, &ParseOptions::default()),
+            "{:?}",
+            path
+        );
+    }
+}
+
+#[test]
+fn commented_programs_parse_back() {
+    use crate::parser::{parse_program_with, ParseOptions};
+    use Instruction::*;
+
+    let program = vec![
+        SetLabel("STOP".to_string()),
+        Push(0),
+        Add,
+        JZero("a\tb".to_string()),
+        EndOfProgram,
+    ];
+    let commented = EncodeOptions {
+        comments: true,
+        ..EncodeOptions::default()
+    };
+    assert_eq!(
+        "push·0    \nadd\t   end\n\n\n",
+        encode_program_with(&[Push(0), Add, EndOfProgram], &commented)
+    );
+
+    for dialect in ["standard", "crlf", "stl", "abc", "custom:abc"].iter() {
+        let dialect: Dialect = dialect.parse().unwrap();
+        let encoded = encode_program_with(
+            &program,
+            &EncodeOptions {
+                dialect: dialect.clone(),
+                comments: true,
+            },
+        );
+        let options = ParseOptions {
+            dialect: dialect.clone(),
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            Ok(program.clone()),
+            parse_program_with(&encoded, &options),
+            "{}",
+            dialect
+        );
+    }
+}
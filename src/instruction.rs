@@ -1,7 +1,11 @@
+use crate::bignum::BigNum;
+
 #[derive(PartialEq, Clone)]
 pub enum Instruction {
     // stack
     Push(i32),
+    /// A literal that does not fit in an `i32`
+    PushBig(BigNum),
     Duplicate,
     CopyNth(i32),
     Swap,
@@ -36,8 +40,9 @@ impl fmt::Debug for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Instruction::Push(v) => write!(f, "Push({})", v),
+            Instruction::PushBig(v) => write!(f, "PushBig({})", v),
             Instruction::Duplicate => write!(f, "Duplicate"),
-            Instruction::CopyNth(v) => write!(f, "Push({})", v),
+            Instruction::CopyNth(v) => write!(f, "CopyNth({})", v),
             Instruction::Swap => write!(f, "Swap"),
             Instruction::Discard => write!(f, "Discard"),
             Instruction::Slide(v) => write!(f, "Slide({})", v),
@@ -65,3 +70,62 @@ impl fmt::Debug for Instruction {
     }
 }
 
+/// The label made of `bits`. Every 8 bits are one character up to '\u{ff}', and the bits
+/// left at the end, if any, are one last character from '\u{102}' to '\u{1ff}': 0x100 plus
+/// the bits behind a leading 1, so that `\t` and ` \t` stay different labels
+pub fn label_from_bits(bits: &[bool]) -> String {
+    let value = |bits: &[bool]| bits.iter().fold(0, |v, &bit| v << 1 | bit as u32);
+    let whole = bits.len() - bits.len() % 8;
+    let mut label: String = bits[..whole]
+        .chunks(8)
+        .filter_map(|byte| std::char::from_u32(value(byte)))
+        .collect();
+    let tail = &bits[whole..];
+    if !tail.is_empty() {
+        label.extend(std::char::from_u32(0x100 + (1 << tail.len() | value(tail))));
+    }
+    label
+}
+
+/// The bits of a label, as read by `label_from_bits`. Characters above '\u{ff}' that are not
+/// the tail of a label give all their binary digits and do not read back the same.
+pub fn label_bits(label: &str) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(label.len() * 8);
+    let mut chars = label.chars().peekable();
+    while let Some(c) = chars.next() {
+        let digits = match c as u32 {
+            tail @ 0x102..=0x1ff if chars.peek().is_none() => {
+                format!("{:b}", tail - 0x100)[1..].to_string()
+            }
+            other => format!("{:08b}", other),
+        };
+        bits.extend(digits.chars().map(|digit| digit == '1'));
+    }
+    bits
+}
+
+#[test]
+fn labels_keep_their_bits() {
+    let tab = label_from_bits(&[true]);
+    let space_tab = label_from_bits(&[false, true]);
+    assert_ne!(tab, space_tab);
+    assert_eq!(
+        "A",
+        label_from_bits(&[false, true, false, false, false, false, false, true])
+    );
+    let samples = [
+        vec![true],
+        vec![false, true],
+        vec![true; 15],
+        vec![false; 8],
+    ];
+    for bits in samples.iter() {
+        assert_eq!(*bits, label_bits(&label_from_bits(bits)));
+    }
+}
+
+#[test]
+fn debug_names_every_instruction() {
+    assert_eq!("CopyNth(2)", format!("{:?}", Instruction::CopyNth(2)));
+    assert_eq!("Push(2)", format!("{:?}", Instruction::Push(2)));
+}
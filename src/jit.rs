@@ -0,0 +1,450 @@
+//! A JIT compiler for x86-64 Linux, enabled with the `jit` cargo feature.
+//!
+//! The stack operations, the arithmetic and the jumps of a program become machine code that
+//! works directly on the stack of the virtual machine. Everything else (I/O, the heap,
+//! subroutines, large literals) and every case the machine code does not handle itself (an
+//! overflow, a division by zero, a stack that is too small or has to grow...) calls back into
+//! the virtual machine, which runs that single op. Errors are therefore the same as with
+//! `run_program`.
+
+use crate::vm::{RuntimeError, VirtualMachine};
+use std::io::{BufRead, Write};
+
+impl<R: BufRead, W: Write> VirtualMachine<R, W, i32> {
+    /// Like `run_program`, with the program compiled to machine code. It falls back to
+    /// `run_program` when debugging, on other platforms, or when the code can not be mapped.
+    pub fn run_jit(&mut self) -> Result<(), RuntimeError> {
+        self.check_integer_mode()?;
+        if self.debug || self.instructions().is_empty() {
+            return self.run_program();
+        }
+        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+        {
+            if let Some(result) = native::run(self) {
+                let _ = self.output.flush();
+                return result;
+            }
+        }
+        self.run_program()
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod native {
+    use crate::bytecode::Op;
+    use crate::vm::{RuntimeError, VirtualMachine};
+    use std::ffi::c_void;
+    use std::io::{BufRead, Write};
+
+    const PROT_READ: i32 = 1;
+    const PROT_WRITE: i32 = 2;
+    const PROT_EXEC: i32 = 4;
+    const MAP_PRIVATE: i32 = 2;
+    const MAP_ANONYMOUS: i32 = 0x20;
+    const PAGE_SIZE: usize = 4096;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: i64,
+        ) -> *mut c_void;
+        fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+        fn munmap(addr: *mut c_void, len: usize) -> i32;
+    }
+
+    // Shared with the machine code, which only knows the offsets of the fields before `vm`
+    #[repr(C)]
+    struct Context<R, W> {
+        stack: *mut i32,
+        len: u64,
+        capacity: u64,
+        ip: u64,
+        // the address of the code of every op
+        table: *const u64,
+        callback: extern "C" fn(*mut Context<R, W>, u64) -> u64,
+        vm: *mut VirtualMachine<R, W, i32>,
+        error: Option<RuntimeError>,
+    }
+
+    const STACK: u8 = 0;
+    const LEN: u8 = 8;
+    const CAPACITY: u8 = 16;
+    const IP: u8 = 24;
+    const TABLE: u8 = 32;
+    const CALLBACK: u8 = 40;
+
+    // Runs the program, None when the code could not be mapped
+    pub(super) fn run<R: BufRead, W: Write>(
+        vm: &mut VirtualMachine<R, W, i32>,
+    ) -> Option<Result<(), RuntimeError>> {
+        let assembly = compile(&vm.bytecode().ops);
+        let code = Code::new(&assembly.code)?;
+        let table: Vec<u64> = assembly
+            .offsets
+            .iter()
+            .map(|offset| code.address as u64 + *offset as u64)
+            .collect();
+
+        let mut context = Context {
+            stack: vm.stack.as_mut_ptr(),
+            len: vm.stack.len() as u64,
+            capacity: vm.stack.capacity() as u64,
+            ip: vm.ip as u64,
+            table: table.as_ptr(),
+            callback: step::<R, W>,
+            vm: vm as *mut _,
+            error: None,
+        };
+        unsafe {
+            let entry: unsafe extern "C" fn(*mut Context<R, W>) = std::mem::transmute(code.address);
+            entry(&mut context);
+            // every exit goes through the callback, which leaves the virtual machine up to date
+            vm.stack.set_len(context.len as usize);
+        }
+        Some(match context.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        })
+    }
+
+    // Called by the machine code to run the op at `ip` with the virtual machine. Returns 0 to
+    // go on with the op at `context.ip`, anything else to stop.
+    extern "C" fn step<R: BufRead, W: Write>(context: *mut Context<R, W>, ip: u64) -> u64 {
+        let context = unsafe { &mut *context };
+        let vm = unsafe { &mut *context.vm };
+        unsafe { vm.stack.set_len(context.len as usize) };
+        vm.ip = ip as usize;
+        let result = vm.step_bytecode();
+        context.stack = vm.stack.as_mut_ptr();
+        context.len = vm.stack.len() as u64;
+        context.capacity = vm.stack.capacity() as u64;
+        context.ip = vm.ip as u64;
+        match result {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(error) => {
+                context.error = Some(error);
+                1
+            }
+        }
+    }
+
+    // Executable memory holding the machine code
+    struct Code {
+        address: *mut c_void,
+        size: usize,
+    }
+
+    impl Code {
+        fn new(bytes: &[u8]) -> Option<Code> {
+            let size = bytes.len().div_ceil(PAGE_SIZE) * PAGE_SIZE;
+            unsafe {
+                let address = mmap(
+                    std::ptr::null_mut(),
+                    size,
+                    PROT_READ | PROT_WRITE,
+                    MAP_PRIVATE | MAP_ANONYMOUS,
+                    -1,
+                    0,
+                );
+                if address as isize == -1 {
+                    return None;
+                }
+                let code = Code { address, size };
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, bytes.len());
+                if mprotect(address, size, PROT_READ | PROT_EXEC) != 0 {
+                    return None;
+                }
+                Some(code)
+            }
+        }
+    }
+
+    impl Drop for Code {
+        fn drop(&mut self) {
+            unsafe {
+                munmap(self.address, self.size);
+            }
+        }
+    }
+
+    // Where a jump goes
+    #[derive(Clone, Copy)]
+    enum Target {
+        // the code of an op
+        Op(usize),
+        // runs an op with the virtual machine
+        Slow(usize),
+        Step,
+        Exit,
+    }
+
+    struct Assembly {
+        code: Vec<u8>,
+        // the offset of the code of every op, and of the end of the program
+        offsets: Vec<usize>,
+    }
+
+    // Registers: rbx holds the context, r12 the address of the stack, r13 its length, r14 its
+    // capacity and r15 the table of the addresses of the ops
+    #[derive(Default)]
+    struct Assembler {
+        code: Vec<u8>,
+        // the position of a 32 bits displacement, and where it goes
+        jumps: Vec<(usize, Target)>,
+    }
+
+    const EAX: u8 = 0;
+    const ECX: u8 = 1;
+    const EDX: u8 = 2;
+
+    impl Assembler {
+        fn emit(&mut self, bytes: &[u8]) {
+            self.code.extend_from_slice(bytes);
+        }
+
+        fn emit_i32(&mut self, value: i32) {
+            self.emit(&value.to_le_bytes());
+        }
+
+        // A jump or a conditional jump with a 32 bits displacement
+        fn jump(&mut self, opcode: &[u8], target: Target) {
+            self.emit(opcode);
+            self.jumps.push((self.code.len(), target));
+            self.emit_i32(0);
+        }
+
+        // mov reg, [r12 + r13 * 4 + slot * 4], where slot -1 is the top of the stack
+        fn load(&mut self, reg: u8, slot: i8) {
+            self.emit(&[0x43, 0x8B, 0x44 | reg << 3, 0xAC, (slot * 4) as u8]);
+        }
+
+        // mov [r12 + r13 * 4 + slot * 4], reg
+        fn store(&mut self, slot: i8, reg: u8) {
+            self.emit(&[0x43, 0x89, 0x44 | reg << 3, 0xAC, (slot * 4) as u8]);
+        }
+
+        // Goes to the slow path unless the stack holds at least n values
+        fn need(&mut self, n: i32, ip: usize) {
+            if n == 1 {
+                // test r13, r13; je
+                self.emit(&[0x4D, 0x85, 0xED]);
+                self.jump(&[0x0F, 0x84], Target::Slow(ip));
+            } else {
+                // cmp r13, n; jb
+                self.emit(&[0x49, 0x81, 0xFD]);
+                self.emit_i32(n);
+                self.jump(&[0x0F, 0x82], Target::Slow(ip));
+            }
+        }
+
+        // Goes to the slow path when there is no room for one more value
+        fn room(&mut self, ip: usize) {
+            // cmp r13, r14; jae
+            self.emit(&[0x4D, 0x39, 0xF5]);
+            self.jump(&[0x0F, 0x83], Target::Slow(ip));
+        }
+
+        fn inc_len(&mut self) {
+            self.emit(&[0x49, 0xFF, 0xC5]);
+        }
+
+        fn dec_len(&mut self) {
+            self.emit(&[0x49, 0xFF, 0xCD]);
+        }
+
+        // mov esi, ip; jmp step
+        fn slow(&mut self, ip: usize) {
+            self.emit(&[0xBE]);
+            self.emit_i32(ip as i32);
+            self.jump(&[0xE9], Target::Step);
+        }
+
+        // mov r12, [rbx + STACK]; mov r13, [rbx + LEN]; mov r14, [rbx + CAPACITY]
+        // mov rax, [rbx + IP]; jmp [r15 + rax * 8]
+        fn dispatch(&mut self) {
+            self.emit(&[0x4C, 0x8B, 0x63, STACK]);
+            self.emit(&[0x4C, 0x8B, 0x6B, LEN]);
+            self.emit(&[0x4C, 0x8B, 0x73, CAPACITY]);
+            self.emit(&[0x48, 0x8B, 0x43, IP]);
+            self.emit(&[0x41, 0xFF, 0x24, 0xC7]);
+        }
+
+        // Pops two values, pushes the result of an operation of eax and ecx that sets the
+        // overflow flag
+        fn arithmetic(&mut self, operation: &[u8], ip: usize) {
+            self.need(2, ip);
+            self.load(EAX, -2);
+            self.load(ECX, -1);
+            self.emit(operation);
+            self.jump(&[0x0F, 0x80], Target::Slow(ip));
+            self.store(-2, EAX);
+            self.dec_len();
+        }
+
+        // Pops two values, pushes their quotient (eax) or remainder (edx), rounded towards
+        // negative infinity. Dividing by 0 or -1 is left to the virtual machine.
+        fn division(&mut self, result: u8, ip: usize) {
+            self.need(2, ip);
+            self.load(ECX, -1);
+            // test ecx, ecx; je
+            self.emit(&[0x85, 0xC9]);
+            self.jump(&[0x0F, 0x84], Target::Slow(ip));
+            // cmp ecx, -1; je
+            self.emit(&[0x83, 0xF9, 0xFF]);
+            self.jump(&[0x0F, 0x84], Target::Slow(ip));
+            self.load(EAX, -2);
+            // cdq; idiv ecx
+            self.emit(&[0x99, 0xF7, 0xF9]);
+            // when the remainder is not 0 and its sign is not the one of the divisor:
+            // test edx, edx; je +10; mov esi, edx; xor esi, ecx; jns +4; dec eax; add edx, ecx
+            self.emit(&[0x85, 0xD2, 0x74, 10, 0x89, 0xD6, 0x31, 0xCE, 0x79, 4]);
+            self.emit(&[0xFF, 0xC8, 0x01, 0xCA]);
+            self.store(-2, result);
+            self.dec_len();
+        }
+
+        // Pops a value, jumps when the condition holds for it
+        fn conditional_jump(&mut self, condition: &[u8], target: usize, ip: usize) {
+            self.need(1, ip);
+            self.dec_len();
+            self.load(EAX, 0);
+            // test eax, eax
+            self.emit(&[0x85, 0xC0]);
+            self.jump(condition, Target::Op(target));
+        }
+
+        fn op(&mut self, op: Op, ip: usize) {
+            match op {
+                Op::Push(v) => {
+                    self.room(ip);
+                    // mov dword [r12 + r13 * 4], v
+                    self.emit(&[0x43, 0xC7, 0x44, 0xAC, 0x00]);
+                    self.emit_i32(v);
+                    self.inc_len();
+                }
+                Op::Duplicate => {
+                    self.need(1, ip);
+                    self.room(ip);
+                    self.load(EAX, -1);
+                    self.store(0, EAX);
+                    self.inc_len();
+                }
+                Op::CopyNth(n) if (0..i32::MAX).contains(&n) => {
+                    self.need(n + 1, ip);
+                    self.room(ip);
+                    // mov rax, r13; sub rax, n + 1; mov eax, [r12 + rax * 4]
+                    self.emit(&[0x4C, 0x89, 0xE8, 0x48, 0x2D]);
+                    self.emit_i32(n + 1);
+                    self.emit(&[0x41, 0x8B, 0x04, 0x84]);
+                    self.store(0, EAX);
+                    self.inc_len();
+                }
+                Op::Swap => {
+                    self.need(2, ip);
+                    self.load(EAX, -1);
+                    self.load(ECX, -2);
+                    self.store(-1, ECX);
+                    self.store(-2, EAX);
+                }
+                Op::Discard => {
+                    self.need(1, ip);
+                    self.dec_len();
+                }
+                Op::Slide(n) if (0..i32::MAX).contains(&n) => {
+                    self.need(n + 1, ip);
+                    self.load(EAX, -1);
+                    // sub r13, n
+                    self.emit(&[0x49, 0x81, 0xED]);
+                    self.emit_i32(n);
+                    self.store(-1, EAX);
+                }
+                // add eax, ecx
+                Op::Add => self.arithmetic(&[0x01, 0xC8], ip),
+                // sub eax, ecx
+                Op::Sub => self.arithmetic(&[0x29, 0xC8], ip),
+                // imul eax, ecx
+                Op::Mul => self.arithmetic(&[0x0F, 0xAF, 0xC1], ip),
+                Op::Div => self.division(EAX, ip),
+                Op::Mod => self.division(EDX, ip),
+                Op::Nop => (),
+                Op::Jump(Some(target)) => self.jump(&[0xE9], Target::Op(target)),
+                // je
+                Op::JZero(Some(target)) => self.conditional_jump(&[0x0F, 0x84], target, ip),
+                // js
+                Op::JNeg(Some(target)) => self.conditional_jump(&[0x0F, 0x88], target, ip),
+                _ => self.slow(ip),
+            }
+        }
+    }
+
+    fn compile(ops: &[Op]) -> Assembly {
+        let mut asm = Assembler::default();
+
+        // save the registers of the caller, keeping the stack aligned on 16 bytes for calls
+        // push rbx; push rbp; push r12; push r13; push r14; push r15; sub rsp, 8
+        asm.emit(&[0x53, 0x55, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+        asm.emit(&[0x48, 0x83, 0xEC, 0x08]);
+        // mov rbx, rdi; mov r15, [rbx + TABLE]
+        asm.emit(&[0x48, 0x89, 0xFB, 0x4C, 0x8B, 0x7B, TABLE]);
+        asm.dispatch();
+
+        let step = asm.code.len();
+        // mov [rbx + LEN], r13; mov rdi, rbx; call [rbx + CALLBACK]; test rax, rax; jnz exit
+        asm.emit(&[
+            0x4C, 0x89, 0x6B, LEN, 0x48, 0x89, 0xDF, 0xFF, 0x53, CALLBACK,
+        ]);
+        asm.emit(&[0x48, 0x85, 0xC0]);
+        asm.jump(&[0x0F, 0x85], Target::Exit);
+        asm.dispatch();
+
+        let exit = asm.code.len();
+        // add rsp, 8; pop r15; pop r14; pop r13; pop r12; pop rbp; pop rbx; ret
+        asm.emit(&[0x48, 0x83, 0xC4, 0x08]);
+        asm.emit(&[
+            0x41, 0x5F, 0x41, 0x5E, 0x41, 0x5D, 0x41, 0x5C, 0x5D, 0x5B, 0xC3,
+        ]);
+
+        let mut offsets = Vec::with_capacity(ops.len() + 1);
+        for (ip, op) in ops.iter().enumerate() {
+            offsets.push(asm.code.len());
+            asm.op(*op, ip);
+        }
+        // running past the last op is an error reported by the virtual machine
+        offsets.push(asm.code.len());
+        asm.slow(ops.len());
+
+        let mut slow_paths = vec![None; ops.len()];
+        // the slow paths are emitted on first use, their own jumps are patched in turn
+        let mut index = 0;
+        while index < asm.jumps.len() {
+            let (position, target) = asm.jumps[index];
+            index += 1;
+            let destination = match target {
+                Target::Op(ip) => offsets[ip],
+                Target::Step => step,
+                Target::Exit => exit,
+                Target::Slow(ip) => match slow_paths[ip] {
+                    Some(offset) => offset,
+                    None => {
+                        let offset = asm.code.len();
+                        asm.slow(ip);
+                        slow_paths[ip] = Some(offset);
+                        offset
+                    }
+                },
+            };
+            let displacement = destination as i32 - (position as i32 + 4);
+            asm.code[position..position + 4].copy_from_slice(&displacement.to_le_bytes());
+        }
+
+        Assembly {
+            code: asm.code,
+            offsets,
+        }
+    }
+}
@@ -1,5 +1,17 @@
+pub mod arithmetic;
 pub mod bignum;
 pub mod instruction;
 pub mod parser;
 pub mod vm;
 pub mod compiler;
+pub mod cst;
+pub mod dialect;
+pub mod stream;
+pub mod encoder;
+pub mod assembler;
+pub mod disassembler;
+pub mod linker;
+pub mod bytecode;
+pub mod c_backend;
+#[cfg(feature = "jit")]
+pub mod jit;
@@ -0,0 +1,152 @@
+use crate::disassembler::label_name;
+use crate::instruction::Instruction;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+/// A label that can not be resolved. Labels are written as in assembly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkError {
+    /// The index of the branch, or of the second definition of the label
+    pub ip: usize,
+    pub kind: LinkErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkErrorKind {
+    /// A branch to a label that is defined nowhere in the program
+    UnknownLabel(String),
+    /// A label that is defined more than once
+    DuplicateLabel(String),
+}
+
+impl fmt::Display for LinkErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkErrorKind::UnknownLabel(label) => write!(f, "unknown label {}", label_name(label)),
+            LinkErrorKind::DuplicateLabel(label) => {
+                write!(f, "label {} is already defined", label_name(label))
+            }
+        }
+    }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (instruction {})", self.kind, self.ip)
+    }
+}
+
+impl Error for LinkError {}
+
+// The index of every label. When a label is defined twice, the last definition wins.
+fn label_indices(instructions: &[Instruction]) -> HashMap<&str, usize> {
+    let mut labels = HashMap::new();
+    for (index, instruction) in instructions.iter().enumerate() {
+        if let Instruction::SetLabel(label) = instruction {
+            labels.insert(label.as_str(), index);
+        }
+    }
+    labels
+}
+
+/// The label a call or a jump goes to
+pub fn branch_label(instruction: &Instruction) -> Option<&str> {
+    match instruction {
+        Instruction::CallSubroutine(label)
+        | Instruction::Jump(label)
+        | Instruction::JZero(label)
+        | Instruction::JNeg(label) => Some(label),
+        _ => None,
+    }
+}
+
+/// For every instruction, the index of the instruction it branches to. It is None for the
+/// instructions that do not branch, and for the branches to unknown labels.
+pub fn resolve(instructions: &[Instruction]) -> Vec<Option<usize>> {
+    let labels = label_indices(instructions);
+    instructions
+        .iter()
+        .map(|instruction| branch_label(instruction).and_then(|label| labels.get(label).cloned()))
+        .collect()
+}
+
+/// Like `resolve`, but every label has to be defined exactly once and every branch has to go
+/// to one of them
+pub fn link(instructions: &[Instruction]) -> Result<Vec<Option<usize>>, LinkError> {
+    match link_errors(instructions).into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(resolve(instructions)),
+    }
+}
+
+/// Every reason why `link` fails, in the order of the instructions
+pub fn link_errors(instructions: &[Instruction]) -> Vec<LinkError> {
+    let targets = resolve(instructions);
+    let mut defined = HashSet::new();
+    let mut errors = Vec::new();
+    for (ip, instruction) in instructions.iter().enumerate() {
+        let kind = match (instruction, branch_label(instruction)) {
+            (Instruction::SetLabel(label), _) if !defined.insert(label) => {
+                LinkErrorKind::DuplicateLabel(label.to_string())
+            }
+            (_, Some(label)) if targets[ip].is_none() => {
+                LinkErrorKind::UnknownLabel(label.to_string())
+            }
+            _ => continue,
+        };
+        errors.push(LinkError { ip, kind });
+    }
+    errors
+}
+
+#[test]
+fn link_resolves_every_branch() {
+    use Instruction::*;
+
+    let program = vec![
+        Jump("b".to_string()),
+        SetLabel("a".to_string()),
+        CallSubroutine("a".to_string()),
+        SetLabel("b".to_string()),
+        JZero("a".to_string()),
+        JNeg("c".to_string()),
+    ];
+    let expected = vec![Some(3), None, Some(1), None, Some(1), None];
+    assert_eq!(expected, resolve(&program));
+    assert_eq!(
+        Err(LinkError {
+            ip: 5,
+            kind: LinkErrorKind::UnknownLabel("c".to_string())
+        }),
+        link(&program)
+    );
+    assert_eq!(Ok(expected[..5].to_vec()), link(&program[..5]));
+}
+
+#[test]
+fn link_rejects_labels_defined_twice() {
+    use Instruction::*;
+
+    let program = vec![
+        SetLabel("a".to_string()),
+        Jump("a".to_string()),
+        SetLabel("a".to_string()),
+        Jump("b".to_string()),
+    ];
+    let errors = link_errors(&program);
+    assert_eq!(
+        vec![
+            LinkError {
+                ip: 2,
+                kind: LinkErrorKind::DuplicateLabel("a".to_string())
+            },
+            LinkError {
+                ip: 3,
+                kind: LinkErrorKind::UnknownLabel("b".to_string())
+            }
+        ],
+        errors
+    );
+    assert_eq!(Err(errors[0].clone()), link(&program));
+}
@@ -1,84 +1,291 @@
+use crate::arithmetic::{IntegerMode, Number};
+use crate::bignum::BigNum;
+use crate::dialect::Dialect;
 use crate::instruction::*;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
 
 type ParseResult<'a, Output> = Result<(&'a str, Output), String>;
 
+/// Settings that change how a program is parsed
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// How number literals that do not fit in an `i32` are handled
+    pub integer_mode: IntegerMode,
+    /// Keep literals that do not fit in an `i32` as `PushBig` instead of applying `integer_mode`.
+    /// The `Promote` mode keeps them as well.
+    pub arbitrary_precision: bool,
+    /// Read numbers made of a sign only as 0, like the reference implementation does,
+    /// instead of reporting an error. Some programs such as `examples/hworld2.ws` need it.
+    pub allow_sign_only_numbers: bool,
+    /// How space, tab and line feed are written in the source
+    pub dialect: Dialect,
+}
 
+/// Why a program could not be parsed, and where in the original source
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Starts at 1
+    pub line: usize,
+    /// Starts at 1, counted in characters (comments included)
+    pub column: usize,
+    /// Byte offset of the error in the source
+    pub offset: usize,
+    /// The whitespace characters of the instruction that were read before the error
+    pub prefix: String,
+    /// The characters that would have been valid at this position. It is empty when no
+    /// character would help, for instance when a number is too large.
+    pub expected: Vec<char>,
+    pub message: String,
+}
 
-// low values are on the right
-pub fn match_int(input: &str) -> ParseResult<i32> {
-    let mut int_value: i32 = 0;
-    let mut offset = 0;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.message, self.line, self.column
+        )?;
+        if !self.prefix.is_empty() {
+            write!(f, " after {}", visible(&self.prefix))?;
+        }
+        if !self.expected.is_empty() {
+            let expected: Vec<String> = self
+                .expected
+                .iter()
+                .map(|c| visible(&c.to_string()))
+                .collect();
+            write!(f, ", expected {}", expected.join(" or "))?;
+        }
+        Ok(())
+    }
+}
 
-    for c in input.chars() {
-        match c {
-            '\t' => {
-                int_value = (int_value << 1) + 1;
-                offset += 1;
-            }
-            ' ' => {
-                int_value <<= 1;
-                offset += 1;
+impl Error for ParseError {}
+
+// Whitespace made readable, the same way `debug` prints it
+fn visible(ws: &str) -> String {
+    ws.chars()
+        .map(|c| match c {
+            '\t' => "[tab]".to_string(),
+            ' ' => "[space]".to_string(),
+            '\n' => "[lf]".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// An error while matching something, `at` characters after its start
+#[derive(Debug)]
+pub(crate) struct Failure {
+    pub(crate) at: usize,
+    pub(crate) expected: Vec<char>,
+    pub(crate) message: String,
+    // the input stopped before what was being matched was complete
+    pub(crate) end_of_input: bool,
+    // when the characters were fine but not their value, where the next instruction starts
+    resume_at: Option<usize>,
+}
+
+impl Failure {
+    fn new(at: usize, expected: &[char], message: String) -> Failure {
+        Failure {
+            at,
+            expected: expected.to_vec(),
+            message,
+            end_of_input: false,
+            resume_at: None,
+        }
+    }
+
+    // The failure of an argument that was read entirely, `rest` being what follows it
+    fn resuming(self, input: &str, rest: &str) -> Failure {
+        Failure {
+            resume_at: Some(input.len() - rest.len()),
+            ..self
+        }
+    }
+
+    fn end_of_input(at: usize, expected: &[char], message: String) -> Failure {
+        Failure {
+            end_of_input: true,
+            ..Failure::new(at, expected, message)
+        }
+    }
+
+    // The same failure, for something that starts n characters earlier
+    fn shifted(mut self, n: usize) -> Failure {
+        self.at += n;
+        self.resume_at = self.resume_at.map(|r| r + n);
+        self
+    }
+}
+
+const SIGN: &[char] = &[' ', '\t'];
+const DIGIT_OR_END: &[char] = &[' ', '\t', '\n'];
+
+// The first character is the sign (space: positive, tab: negative), then comes the
+// binary value with low values on the right
+pub fn match_int(input: &str) -> ParseResult<'_, i32> {
+    match_int_with_mode(input, IntegerMode::default())
+}
+
+pub fn match_int_with_mode(input: &str, mode: IntegerMode) -> ParseResult<'_, i32> {
+    let (rest, value) = read_int(input).map_err(|failure| failure.message)?;
+    match fit_in_i32(value, mode) {
+        Ok(v) => Ok((rest, v)),
+        Err(failure) => Err(failure.message),
+    }
+}
+
+// Same encoding as match_int, without any limit on the size of the value
+pub fn match_bignum(input: &str) -> ParseResult<'_, BigNum> {
+    match read_int(input) {
+        Ok((rest, Int::Small(v))) => Ok((rest, BigNum::from(v))),
+        Ok((rest, Int::Big(v))) => Ok((rest, v)),
+        Err(failure) => Err(failure.message),
+    }
+}
+
+// The value of an int, only kept as a BigNum when it does not fit in an i32
+enum Int {
+    Small(i32),
+    Big(BigNum),
+}
+
+fn read_int(input: &str) -> Result<(&str, Int), Failure> {
+    let mut negative = false;
+    // the magnitude, until it is too large for an i32
+    let mut small: i64 = 0;
+    let mut big: Option<BigNum> = None;
+
+    for (offset, c) in input.chars().enumerate() {
+        let bit = match c {
+            '\t' if offset == 0 => {
+                negative = true;
+                continue;
             }
+            ' ' if offset == 0 => continue,
+            '\t' => 1,
+            ' ' => 0,
             '\n' => {
-                return if offset > 0 {
-                    Ok((&input[(offset + 1)..], int_value))
-                } else {
-                    Err("The int terminates with no data".to_string())
+                return match offset {
+                    0 => Err(Failure::new(
+                        0,
+                        SIGN,
+                        "The int terminates with no data".to_string(),
+                    )),
+                    1 => Err(Failure::new(
+                        1,
+                        SIGN,
+                        "The int has a sign but no digits".to_string(),
+                    )),
+                    _ => {
+                        let value = match big {
+                            Some(v) if negative => Int::Big(v.neg()),
+                            Some(v) => Int::Big(v),
+                            None => {
+                                let v = if negative { -small } else { small };
+                                match i32::try_from(v) {
+                                    Ok(v) => Int::Small(v),
+                                    Err(_) => Int::Big(BigNum::from(v)),
+                                }
+                            }
+                        };
+                        Ok((&input[(offset + 1)..], value))
+                    }
                 }
             }
             c => {
-                return Err(format!(
-                    "{} is not a valid character when trying to match an int",
-                    c
-                ))
+                let expected = if offset < 2 { SIGN } else { DIGIT_OR_END };
+                return Err(Failure::new(
+                    offset,
+                    expected,
+                    format!("{} is not a valid character when trying to match an int", c),
+                ));
+            }
+        };
+        match &mut big {
+            Some(v) => *v = v.mul(&BigNum::from(2)).add(&BigNum::from(bit)),
+            None => {
+                small = small * 2 + bit;
+                if small > 1 << 32 {
+                    big = Some(BigNum::from(small));
+                }
             }
         }
     }
 
-    Err("an int should terminate with a \\n".to_string())
+    let length = input.chars().count();
+    let expected = if length < 2 { SIGN } else { DIGIT_OR_END };
+    Err(Failure::end_of_input(
+        length,
+        expected,
+        "an int should terminate with a \\n".to_string(),
+    ))
 }
 
-pub fn match_string(input: &str) -> ParseResult<String> {
-    let mut matched = String::new();
+// Like read_int, reading a number made of a sign only as 0 when the options allow it
+fn read_number<'a>(input: &'a str, options: &ParseOptions) -> Result<(&'a str, Int), Failure> {
+    if options.allow_sign_only_numbers && (input.starts_with(" \n") || input.starts_with("\t\n"))
+    {
+        return Ok((&input[2..], Int::Small(0)));
+    }
+    read_int(input)
+}
+
+fn fit_in_i32(value: Int, mode: IntegerMode) -> Result<i32, Failure> {
+    match value {
+        Int::Small(v) => Ok(v),
+        Int::Big(v) => i32::from_bignum(&v, mode)
+            .map_err(|_| Failure::new(0, &[], "The int does not fit in 32 bits".to_string())),
+    }
+}
+
+pub fn match_string(input: &str) -> ParseResult<'_, String> {
+    read_label(input).map_err(|failure| failure.message)
+}
 
-    let mut curr_byte: u8 = 0u8;
-    let mut offset: usize = 0;
+fn read_label(input: &str) -> Result<(&str, String), Failure> {
+    let mut bits = Vec::new();
 
     for c in input.chars() {
         match c {
-            '\t' => {
-                curr_byte = (curr_byte << 1) + 1;
-                offset += 1;
-            }
-            ' ' => {
-                curr_byte <<= 1;
-                offset += 1;
-            }
+            '\t' => bits.push(true),
+            ' ' => bits.push(false),
             '\n' => {
-                if offset % 8 != 0 {
-                    matched += &(curr_byte as char).to_string();
-                }
-                return if !matched.is_empty() {
-                    Ok((&input[(offset + 1)..], matched))
+                return if !bits.is_empty() {
+                    Ok((&input[(bits.len() + 1)..], label_from_bits(&bits)))
                 } else {
-                    Err("The string terminates with no data".to_string())
+                    Err(Failure::new(
+                        0,
+                        SIGN,
+                        "The string terminates with no data".to_string(),
+                    ))
                 };
             }
             c => {
-                return Err(format!(
-                    "{} is not a valid character when trying to match a string",
-                    c
-                ))
+                let expected = if bits.is_empty() { SIGN } else { DIGIT_OR_END };
+                return Err(Failure::new(
+                    bits.len(),
+                    expected,
+                    format!(
+                        "{} is not a valid character when trying to match a string",
+                        c
+                    ),
+                ));
             }
         }
-
-        if offset % 8 == 0 {
-            matched += &(curr_byte as char).to_string();
-            curr_byte = 0;
-        }
     }
 
-    Err("a string should terminate with a \\n".to_string())
+    let offset = bits.len();
+    let expected = if offset == 0 { SIGN } else { DIGIT_OR_END };
+    Err(Failure::end_of_input(
+        offset,
+        expected,
+        "a string should terminate with a \\n".to_string(),
+    ))
 }
 
 pub fn debug(ws_program: &str) {
@@ -91,157 +298,172 @@ pub fn debug(ws_program: &str) {
     }
 }
 
-fn create_push_instruction(x: (&str, i32)) -> ParseResult<Instruction> {
-    Ok((x.0, Instruction::Push(x.1)))
-}
-
-fn create_copynth_instruction(x: (&str, i32)) -> ParseResult<Instruction> {
-    Ok((x.0, Instruction::CopyNth(x.1)))
+// What follows the characters identifying an instruction
+pub(crate) enum Form {
+    Simple(Instruction),
+    Push,
+    Number(fn(i32) -> Instruction),
+    Label(fn(String) -> Instruction),
 }
 
-fn create_slide_instruction(x: (&str, i32)) -> ParseResult<Instruction> {
-    Ok((x.0, Instruction::Slide(x.1)))
+impl Form {
+    // Whether the instructions read with this form look like this one
+    pub(crate) fn creates(&self, instruction: &Instruction) -> bool {
+        match (self, instruction) {
+            (Form::Simple(simple), _) => simple == instruction,
+            (Form::Push, Instruction::Push(_)) | (Form::Push, Instruction::PushBig(_)) => true,
+            (Form::Number(create), Instruction::CopyNth(v))
+            | (Form::Number(create), Instruction::Slide(v)) => create(*v) == *instruction,
+            (Form::Label(create), Instruction::SetLabel(label))
+            | (Form::Label(create), Instruction::CallSubroutine(label))
+            | (Form::Label(create), Instruction::Jump(label))
+            | (Form::Label(create), Instruction::JZero(label))
+            | (Form::Label(create), Instruction::JNeg(label)) => {
+                create(label.clone()) == *instruction
+            }
+            _ => false,
+        }
+    }
 }
 
-fn create_setlabel_instruction(x: (&str, String)) -> ParseResult<Instruction> {
-    Ok((x.0, Instruction::SetLabel(x.1)))
-}
+// Every instruction, as its IMP followed by its command
+pub(crate) const INSTRUCTIONS: &[(&str, Form)] = &[
+    // stack
+    ("  ", Form::Push),
+    (" \n ", Form::Simple(Instruction::Duplicate)),
+    (" \t ", Form::Number(Instruction::CopyNth)),
+    (" \n\t", Form::Simple(Instruction::Swap)),
+    (" \n\n", Form::Simple(Instruction::Discard)),
+    (" \t\n", Form::Number(Instruction::Slide)),
+    // arithmetic
+    ("\t   ", Form::Simple(Instruction::Add)),
+    ("\t  \t", Form::Simple(Instruction::Sub)),
+    ("\t  \n", Form::Simple(Instruction::Mul)),
+    ("\t \t ", Form::Simple(Instruction::Div)),
+    ("\t \t\t", Form::Simple(Instruction::Mod)),
+    // heap
+    ("\t\t ", Form::Simple(Instruction::Store)),
+    ("\t\t\t", Form::Simple(Instruction::Retrieve)),
+    // flow control
+    ("\n  ", Form::Label(Instruction::SetLabel)),
+    ("\n \t", Form::Label(Instruction::CallSubroutine)),
+    ("\n \n", Form::Label(Instruction::Jump)),
+    ("\n\t ", Form::Label(Instruction::JZero)),
+    ("\n\t\t", Form::Label(Instruction::JNeg)),
+    ("\n\t\n", Form::Simple(Instruction::EndOfSubroutine)),
+    ("\n\n\n", Form::Simple(Instruction::EndOfProgram)),
+    // I/O
+    ("\t\n  ", Form::Simple(Instruction::PrintChar)),
+    ("\t\n \t", Form::Simple(Instruction::PrintInt)),
+    ("\t\n\t ", Form::Simple(Instruction::ReadChar)),
+    ("\t\n\t\t", Form::Simple(Instruction::ReadInt)),
+];
 
-fn create_callsubroutine_instruction(x: (&str, String)) -> ParseResult<Instruction> {
-    Ok((x.0, Instruction::CallSubroutine(x.1)))
+// Parses the first instruction of a program made of whitespace only
+pub fn parse_instruction(ws_program: &str) -> ParseResult<'_, Instruction> {
+    parse_instruction_with(ws_program, &ParseOptions::default())
 }
 
-fn create_jump_instruction(x: (&str, String)) -> ParseResult<Instruction> {
-    Ok((x.0, Instruction::Jump(x.1)))
+pub fn parse_instruction_with<'a>(
+    ws_program: &'a str,
+    options: &ParseOptions,
+) -> ParseResult<'a, Instruction> {
+    read_instruction(ws_program, options).map_err(|failure| failure.message)
 }
 
-fn create_jzero_instruction(x: (&str, String)) -> ParseResult<Instruction> {
-    Ok((x.0, Instruction::JZero(x.1)))
-}
+pub(crate) fn read_instruction<'a>(
+    ws_program: &'a str,
+    options: &ParseOptions,
+) -> Result<(&'a str, Instruction), Failure> {
+    // only whitespace characters are consumed, so the length in bytes is the length in chars
+    let mut length = 0;
+    loop {
+        let prefix = &ws_program[..length];
+        if let Some((_, form)) = INSTRUCTIONS.iter().find(|(p, _)| *p == prefix) {
+            return read_argument(form, &ws_program[length..], options).map_err(|failure| {
+                if failure.end_of_input {
+                    Failure {
+                        message: truncated(prefix),
+                        ..failure.shifted(length)
+                    }
+                } else {
+                    failure.shifted(length)
+                }
+            });
+        }
 
-fn create_jneg_instruction(x: (&str, String)) -> ParseResult<Instruction> {
-    Ok((x.0, Instruction::JNeg(x.1)))
+        let expected: Vec<char> = DIGIT_OR_END
+            .iter()
+            .filter(|c| {
+                INSTRUCTIONS
+                    .iter()
+                    .any(|(p, _)| p.starts_with(prefix) && p[length..].starts_with(**c))
+            })
+            .cloned()
+            .collect();
+        match ws_program[length..].chars().next() {
+            Some(c) if expected.contains(&c) => length += 1,
+            Some(c) => {
+                return Err(Failure::new(
+                    length,
+                    &expected,
+                    format!("{} is an unexpected character", visible(&c.to_string())),
+                ))
+            }
+            None => return Err(Failure::end_of_input(length, &expected, truncated(prefix))),
+        }
+    }
 }
 
-fn parse_start_space<'a >(chars: &Vec<char>, ws_program: &'a str) -> ParseResult<'a, Instruction> {
-    if chars[1] == ' ' {
-        match_int(&ws_program[2..]).and_then(create_push_instruction)
-    } else if chars[1] == '\n' {
-        if chars[2] == ' ' {
-            Ok((&ws_program[3..], Instruction::Duplicate))
-        } else if chars[2] == '\t' {
-            Ok((&ws_program[3..], Instruction::Swap))
-        } else if chars[2] == '\n' {
-            Ok((&ws_program[3..], Instruction::Discard))
-        } else {
-            Err(format!("{} is an unexpected character", chars[2]))
-        }
-    } else if chars[1] == '\t' {
-        if chars[2] == ' ' {
-            match_int(&ws_program[3..]).and_then(create_copynth_instruction)
-        } else if chars[2] == '\n' {
-            match_int(&ws_program[3..]).and_then(create_slide_instruction)
-        } else {
-            Err(format!("{} is an unexpected character", chars[2]))
-        }
+// The message for a program that stops in the middle of an instruction
+fn truncated(prefix: &str) -> String {
+    let group = if prefix.starts_with(' ') {
+        "a stack manipulation instruction"
+    } else if prefix.starts_with("\t ") {
+        "an arithmetic instruction"
+    } else if prefix.starts_with("\t\t") {
+        "a heap access instruction"
+    } else if prefix.starts_with("\t\n") {
+        "an I/O instruction"
+    } else if prefix.starts_with('\n') {
+        "a flow control instruction"
     } else {
-        Err(format!("{} is an unexpected character", chars[1]))
-    }
+        "an arithmetic, heap access or I/O instruction"
+    };
+    format!("unexpected end of program while parsing {}", group)
 }
 
-fn parse_instruction(ws_program: &str) -> ParseResult<Instruction> {
-    let chars: Vec<char> = ws_program.chars().collect();
-    match chars[0] {
-        ' ' => parse_start_space(&chars, &ws_program),
-        '\t' => {
-            if chars[1] == ' ' {
-                if chars[2] == ' ' {
-                    if chars[3] == ' ' {
-                        Ok((&ws_program[4..], Instruction::Add))
-                    } else if chars[3] == '\t' {
-                        Ok((&ws_program[4..], Instruction::Sub))
-                    } else if chars[3] == '\n' {
-                        Ok((&ws_program[4..], Instruction::Mul))
-                    } else {
-                        Err(format!("{} is an unexpected character", chars[3]))
-                    }
-                } else if chars[2] == '\t' {
-                    if chars[3] == ' ' {
-                        Ok((&ws_program[4..], Instruction::Div))
-                    } else if chars[3] == '\t' {
-                        Ok((&ws_program[4..], Instruction::Mod))
-                    } else {
-                        Err(format!("{} is an unexpected character", chars[3]))
-                    }
-                } else {
-                    Err(format!("{} is an unexpected character", chars[2]))
-                }
-            } else if chars[1] == '\t' {
-                if chars[2] == ' ' {
-                    Ok((&ws_program[3..], Instruction::Store))
-                } else if chars[2] == '\t' {
-                    Ok((&ws_program[3..], Instruction::Retrieve))
-                } else {
-                    Err(format!("{} is an unexpected character", chars[2]))
+fn read_argument<'a>(
+    form: &Form,
+    input: &'a str,
+    options: &ParseOptions,
+) -> Result<(&'a str, Instruction), Failure> {
+    match form {
+        Form::Simple(instruction) => Ok((input, instruction.clone())),
+        Form::Push => {
+            let (rest, value) = read_number(input, options)?;
+            let promote = options.integer_mode == IntegerMode::Promote;
+            match value {
+                Int::Big(v) if options.arbitrary_precision || promote => {
+                    Ok((rest, Instruction::PushBig(v)))
                 }
-            } else if chars[1] == '\n' {
-                if chars[2] == ' ' {
-                    if chars[3] == ' ' {
-                        Ok((&ws_program[4..], Instruction::PrintChar))
-                    } else if chars[3] == '\t' {
-                        Ok((&ws_program[4..], Instruction::PrintInt))
-                    } else {
-                        Err(format!("{} is an unexpected character", chars[3]))
-                    }
-                } else if chars[2] == '\t' {
-                    if chars[3] == ' ' {
-                        Ok((&ws_program[4..], Instruction::ReadChar))
-                    } else if chars[3] == '\t' {
-                        Ok((&ws_program[4..], Instruction::ReadInt))
-                    } else {
-                        Err(format!("{} is an unexpected character", chars[3]))
-                    }
-                } else {
-                    Err(format!("{} is an unexpected character", chars[2]))
+                value => {
+                    let value = fit_in_i32(value, options.integer_mode)
+                        .map_err(|f| f.resuming(input, rest))?;
+                    Ok((rest, Instruction::Push(value)))
                 }
-            } else {
-                Err(format!("{} is an unexpected character", chars[1]))
             }
         }
-        '\n' => {
-            if chars[1] == ' ' {
-                if chars[2] == ' ' {
-                    match_string(&ws_program[3..]).and_then(create_setlabel_instruction)
-                } else if chars[2] == '\t' {
-                    match_string(&ws_program[3..]).and_then(create_callsubroutine_instruction)
-                } else if chars[2] == '\n' {
-                    match_string(&ws_program[3..]).and_then(create_jump_instruction)
-                } else {
-                    Err(format!("{} is an unexpected character", chars[2]))
-                }
-            } else if chars[1] == '\n' {
-                if chars[2] == ' ' || chars[2] == '\t' {
-                    Err(format!("{} is an unexpected character", chars[2]))
-                } else if chars[2] == '\n' {
-                    Ok((&ws_program[3..], Instruction::EndOfProgram))
-                } else {
-                    Err(format!("{} is an unexpected character", chars[2]))
-                }
-            } else if chars[1] == '\t' {
-                if chars[2] == ' ' {
-                    match_string(&ws_program[3..]).and_then(create_jzero_instruction)
-                } else if chars[2] == '\t' {
-                    match_string(&ws_program[3..]).and_then(create_jneg_instruction)
-                } else if chars[2] == '\n' {
-                    Ok((&ws_program[3..], Instruction::EndOfSubroutine))
-                } else {
-                    Err(format!("{} is an unexpected character", chars[2]))
-                }
-            } else {
-                Err(format!("{} is an unexpected character", chars[1]))
-            }
+        Form::Number(create) => {
+            let (rest, value) = read_number(input, options)?;
+            let value =
+                fit_in_i32(value, options.integer_mode).map_err(|f| f.resuming(input, rest))?;
+            Ok((rest, create(value)))
+        }
+        Form::Label(create) => {
+            let (rest, label) = read_label(input)?;
+            Ok((rest, create(label)))
         }
-        _ => Err(format!("{} is an unexpected character", chars[0])),
     }
 }
 
@@ -253,37 +475,171 @@ pub enum Token {
 }
 
 pub fn tokenize(ws_program: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    ws_program
-        .chars()
-        .for_each(|c| {
-            match c {
-                ' ' =>  tokens.push(Token::Whitspace),
-                '\t' => tokens.push(Token::Tab),
-                '\n' => tokens.push(Token::LineFeed),
-                _ => {} 
-            }
-        });
-    tokens
-}
-
-pub fn parse_program(ws_program: &str) -> Result<Vec<Instruction>, &str> {
-    let mut ws_program: &str = &(ws_program
-        .chars()
-        .filter(|c| *c == ' ' || *c == '\t' || *c == '\n')
-        .collect::<String>());
-    let mut instructions: Vec<Instruction> = Vec::new();
-    while ws_program != "" {
-        let result = parse_instruction(ws_program);
-        match result {
+    tokenize_with(ws_program, &Dialect::Standard)
+}
+
+pub fn tokenize_with(ws_program: &str, dialect: &Dialect) -> Vec<Token> {
+    dialect
+        .scan(ws_program)
+        .iter()
+        .map(|t| match t.0 {
+            ' ' => Token::Whitspace,
+            '\t' => Token::Tab,
+            _ => Token::LineFeed,
+        })
+        .collect()
+}
+
+pub fn parse_program(ws_program: &str) -> Result<Vec<Instruction>, ParseError> {
+    parse_program_with(ws_program, &ParseOptions::default())
+}
+
+pub fn parse_program_with(
+    ws_program: &str,
+    options: &ParseOptions,
+) -> Result<Vec<Instruction>, ParseError> {
+    let program = parse_program_spanned(ws_program, options)?;
+    Ok(program.into_iter().map(|s| s.instruction).collect())
+}
+
+/// Where an instruction comes from in the original source, comments included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    /// Byte offset of the first character of the instruction
+    pub start: usize,
+    /// Byte offset just after the last character of the instruction
+    pub end: usize,
+    /// Line of the first character, starting at 1
+    pub line: usize,
+    /// Column of the first character, starting at 1 and counted in characters
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedInstruction {
+    pub instruction: Instruction,
+    pub span: Span,
+}
+
+// Like parse_program_with, keeping the position of every instruction in the source
+pub fn parse_program_spanned(
+    ws_program: &str,
+    options: &ParseOptions,
+) -> Result<Vec<SpannedInstruction>, ParseError> {
+    let (instructions, mut errors) = parse(ws_program, options, false);
+    match errors.pop() {
+        Some(error) => Err(error),
+        None => Ok(instructions),
+    }
+}
+
+/// Parses as much as possible of a program: after an invalid instruction, the parser skips
+/// the unexpected character, then more characters until an instruction can be read again.
+/// It returns every instruction that could be read along with every error, in the order they
+/// appear in the source.
+pub fn parse_program_recovering(
+    ws_program: &str,
+    options: &ParseOptions,
+) -> (Vec<SpannedInstruction>, Vec<ParseError>) {
+    parse(ws_program, options, true)
+}
+
+fn parse(
+    ws_program: &str,
+    options: &ParseOptions,
+    recover: bool,
+) -> (Vec<SpannedInstruction>, Vec<ParseError>) {
+    // comments are dropped, but the position of every token that is kept is remembered
+    let tokens = options.dialect.scan(ws_program);
+    let filtered: String = tokens.iter().map(|t| t.0).collect();
+    let mut locator = Locator::new(ws_program);
+    let mut start = 0;
+    let mut instructions: Vec<SpannedInstruction> = Vec::new();
+    let mut errors: Vec<ParseError> = Vec::new();
+    // set while skipping characters after an error, so that it is only reported once. Running
+    // out of input is still reported, as skipping more characters can not help.
+    let mut recovering = false;
+    while start < filtered.len() {
+        match read_instruction(&filtered[start..], options) {
             Ok((rest_of_program, instruction)) => {
-                ws_program = rest_of_program;
-                instructions.push(instruction);
+                let end = filtered.len() - rest_of_program.len();
+                let (line, column) = locator.locate(tokens[start].1);
+                instructions.push(SpannedInstruction {
+                    instruction,
+                    span: Span {
+                        start: tokens[start].1,
+                        end: tokens[end - 1].2,
+                        line,
+                        column,
+                    },
+                });
+                start = end;
+                recovering = false;
+            }
+            Err(failure) if recovering && !failure.end_of_input => start += 1,
+            Err(failure) => {
+                let position = start + failure.at;
+                let offset = tokens.get(position).map_or(ws_program.len(), |t| t.1);
+                let (line, column) = locator.locate(offset);
+                errors.push(ParseError {
+                    line,
+                    column,
+                    offset,
+                    prefix: filtered[start..position].to_string(),
+                    expected: failure.expected,
+                    message: failure.message,
+                });
+                if !recover || failure.end_of_input {
+                    break;
+                }
+                // the invalid prefix is dropped along with the unexpected character
+                match failure.resume_at {
+                    Some(resume_at) => start += resume_at,
+                    None => {
+                        start = position + 1;
+                        recovering = true;
+                    }
+                }
             }
-            Err(_) => return Err("unable to parse the current instruction"),
         }
     }
-    Ok(instructions)
+    (instructions, errors)
+}
+
+// Gives the line and column of increasing offsets, without reading the source again
+struct Locator<'a> {
+    source: &'a str,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Locator<'a> {
+    fn new(source: &'a str) -> Locator<'a> {
+        Locator {
+            source,
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn locate(&mut self, offset: usize) -> (usize, usize) {
+        // the recovering parser can go back a little after an error
+        if offset < self.offset {
+            *self = Locator::new(self.source);
+        }
+        for c in self.source[self.offset..offset].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.offset = offset;
+        (self.line, self.column)
+    }
 }
 
 
@@ -320,23 +676,101 @@ fn match_integer_works() {
         Err("an int should terminate with a \\n".to_string()),
         match_int("\t")
     );
-    assert_eq!(Ok(("", 0)), match_int(" \n"));
-    assert_eq!(Ok(("", 1)), match_int("\t\n"));
-    assert_eq!(Ok(("abc", 1)), match_int("\t\nabc"));
+    assert_eq!(
+        Err("The int has a sign but no digits".to_string()),
+        match_int(" \n")
+    );
+    assert_eq!(
+        Err("The int has a sign but no digits".to_string()),
+        match_int("\t\n")
+    );
+    assert_eq!(Ok(("", 0)), match_int("  \n"));
+    assert_eq!(Ok(("", 0)), match_int("\t \n"));
     assert_eq!(Ok(("", 1)), match_int(" \t\n"));
-    assert_eq!(Ok(("", 2)), match_int("\t \n"));
-    assert_eq!(Ok(("", 4)), match_int("\t  \n"));
-    assert_eq!(Ok(("", 8)), match_int("\t   \n"));
+    assert_eq!(Ok(("abc", 1)), match_int(" \t\nabc"));
+    assert_eq!(Ok(("", -1)), match_int("\t\t\n"));
+    assert_eq!(Ok(("", 1)), match_int("  \t\n"));
+    assert_eq!(Ok(("", 2)), match_int(" \t \n"));
+    assert_eq!(Ok(("", 4)), match_int(" \t  \n"));
     assert_eq!(Ok(("", 8)), match_int(" \t   \n"));
-    assert_eq!(Ok(("", 3)), match_int("\t\t\n"));
-    assert_eq!(Ok(("", 27)), match_int("\t\t \t\t\n"));
-    assert_eq!(Ok(("abc", 27)), match_int("\t\t \t\t\nabc"));
+    assert_eq!(Ok(("", -8)), match_int("\t\t   \n"));
+    assert_eq!(Ok(("", 3)), match_int(" \t\t\n"));
+    assert_eq!(Ok(("", 27)), match_int(" \t\t \t\t\n"));
+    assert_eq!(Ok(("abc", -27)), match_int("\t\t\t \t\t\nabc"));
     assert_eq!(
         Err("an int should terminate with a \\n".to_string()),
         match_int("\t\t \t\t")
     );
 }
 
+#[test]
+fn match_integer_overflow_works() {
+    let max = format!(" {}\n", "\t".repeat(31));
+    let min = format!("\t\t{}\n", " ".repeat(31));
+    let too_large = format!(" \t{}\n", " ".repeat(31));
+    let too_small = format!("\t\t{}\t\n", " ".repeat(30));
+    assert_eq!(Ok(("", i32::MAX)), match_int(&max));
+    assert_eq!(Ok(("", i32::MIN)), match_int(&min));
+    assert_eq!(
+        Err("The int does not fit in 32 bits".to_string()),
+        match_int(&too_small)
+    );
+    assert_eq!(
+        Ok(("", i32::MAX)),
+        match_int_with_mode(&too_small, IntegerMode::Wrapping)
+    );
+    assert_eq!(
+        Ok(("", i32::MIN)),
+        match_int_with_mode(&too_small, IntegerMode::Saturating)
+    );
+    assert_eq!(
+        Err("The int does not fit in 32 bits".to_string()),
+        match_int(&too_large)
+    );
+    assert_eq!(
+        Ok(("", i32::MIN)),
+        match_int_with_mode(&too_large, IntegerMode::Wrapping)
+    );
+    assert_eq!(
+        Ok(("", i32::MAX)),
+        match_int_with_mode(&too_large, IntegerMode::Saturating)
+    );
+}
+
+#[test]
+fn match_bignum_works() {
+    // 2^64 + 1
+    let literal = format!(" \t{}\t\nabc", " ".repeat(63));
+    assert_eq!(
+        Ok(("abc", "18446744073709551617".parse().unwrap())),
+        match_bignum(&literal)
+    );
+    assert_eq!(Ok(("", BigNum::from(5))), match_bignum(" \t \t\n"));
+    assert_eq!(Ok(("", BigNum::from(-5))), match_bignum("\t\t \t\n"));
+    assert_eq!(
+        Err("The int terminates with no data".to_string()),
+        match_bignum("\n")
+    );
+
+    let push = format!("  {}", literal);
+    assert_eq!(
+        Err("The int does not fit in 32 bits".to_string()),
+        parse_instruction(&push)
+    );
+    let options = ParseOptions {
+        arbitrary_precision: true,
+        ..ParseOptions::default()
+    };
+    assert_eq!(
+        Ok(("abc", Instruction::PushBig("18446744073709551617".parse().unwrap()))),
+        parse_instruction_with(&push, &options)
+    );
+    assert_eq!(
+        Ok(("abc", Instruction::Push(5))),
+        parse_instruction_with("   \t \t\nabc", &options)
+    );
+}
+
 #[test]
 fn match_string_works() {
     assert_eq!(
@@ -360,53 +794,54 @@ fn match_string_works() {
         match_string("\t")
     );
     assert_eq!(
-        Ok(("", vec![0 as char].into_iter().collect::<String>())),
+        Ok(("", "\u{102}".to_string())),
         match_string(" \n")
     );
     assert_eq!(
-        Ok(("", vec![1 as char].into_iter().collect::<String>())),
+        Ok(("", "\u{103}".to_string())),
         match_string("\t\n")
     );
     assert_eq!(
-        Ok(("abc", vec![1 as char].into_iter().collect::<String>())),
+        Ok(("abc", "\u{103}".to_string())),
         match_string("\t\nabc")
     );
     assert_eq!(
-        Ok(("", vec![1 as char].into_iter().collect::<String>())),
+        Ok(("", "\u{105}".to_string())),
         match_string(" \t\n")
     );
+    assert_ne!(match_string("\t\n"), match_string(" \t\n"));
     assert_eq!(
-        Ok(("", vec![8 as char].into_iter().collect::<String>())),
+        Ok(("", "\u{128}".to_string())),
         match_string(" \t   \n")
     );
     assert_eq!(
-        Ok(("", vec![3 as char].into_iter().collect::<String>())),
+        Ok(("", "\u{107}".to_string())),
         match_string("\t\t\n")
     );
     assert_eq!(
-        Ok(("", vec![27 as char].into_iter().collect::<String>())),
+        Ok(("", "\u{13b}".to_string())),
         match_string("\t\t \t\t\n")
     );
     assert_eq!(
-        Ok(("abc", vec![27 as char].into_iter().collect::<String>())),
+        Ok(("abc", "\u{13b}".to_string())),
         match_string("\t\t \t\t\nabc")
     );
-    assert_eq!(Ok(("abc", "A".to_string())), match_string("\t     \t\nabc"));
+    assert_eq!(Ok(("abc", "A".to_string())), match_string(" \t     \t\nabc"));
     assert_eq!(
         Ok(("abc", "a".to_string())),
-        match_string("\t\t    \t\nabc")
+        match_string(" \t\t    \t\nabc")
     );
     assert_eq!(
         Ok((
             "",
-            vec![3 as char, 1 as char].into_iter().collect::<String>()
+            vec![3 as char, '\u{103}'].into_iter().collect::<String>()
         )),
         match_string("      \t\t\t\n")
     );
     assert_eq!(
         Ok((
             "",
-            vec![3 as char, 0 as char].into_iter().collect::<String>()
+            vec![3 as char, '\u{102}'].into_iter().collect::<String>()
         )),
         match_string("      \t\t \n")
     );
@@ -432,19 +867,35 @@ fn parse_instruction_works() {
     );
     assert_eq!(
         Ok(("abc", Instruction::Push(1))),
-        parse_instruction("  \t\nabc")
+        parse_instruction("   \t\nabc")
     );
     assert_eq!(
         Ok(("abc", Instruction::Push(2))),
-        parse_instruction("  \t \nabc")
+        parse_instruction("   \t \nabc")
     );
     assert_eq!(
         Ok(("abc", Instruction::Push(2))),
-        parse_instruction("   \t \nabc")
+        parse_instruction("    \t \nabc")
     );
     assert_eq!(
         Ok(("abc", Instruction::Push(5))),
-        parse_instruction("  \t \t\nabc")
+        parse_instruction("   \t \t\nabc")
+    );
+    assert_eq!(
+        Ok(("abc", Instruction::Push(-5))),
+        parse_instruction("  \t\t \t\nabc")
+    );
+    assert_eq!(
+        Err("The int has a sign but no digits".to_string()),
+        parse_instruction("  \t\nabc")
+    );
+    let lenient = ParseOptions {
+        allow_sign_only_numbers: true,
+        ..ParseOptions::default()
+    };
+    assert_eq!(
+        Ok(("abc", Instruction::Push(0))),
+        parse_instruction_with("  \t\nabc", &lenient)
     );
 
     assert_eq!(
@@ -453,7 +904,27 @@ fn parse_instruction_works() {
     );
     assert_eq!(
         Ok(("abc", Instruction::CopyNth(3))),
-        parse_instruction(" \t \t\t\nabc")
+        parse_instruction(" \t  \t\t\nabc")
+    );
+    assert_eq!(
+        Ok(("abc", Instruction::CopyNth(-3))),
+        parse_instruction(" \t \t\t\t\nabc")
+    );
+    assert_eq!(
+        Ok(("abc", Instruction::Slide(2))),
+        parse_instruction(" \t\n \t \nabc")
+    );
+    assert_eq!(
+        Ok(("abc", Instruction::Slide(-2))),
+        parse_instruction(" \t\n\t\t \nabc")
+    );
+    assert_eq!(
+        Err("The int has a sign but no digits".to_string()),
+        parse_instruction(" \t\n\t\nabc")
+    );
+    assert_eq!(
+        Ok(("abc", Instruction::Slide(0))),
+        parse_instruction_with(" \t\n\t\nabc", &lenient)
     );
     assert_eq!(
         Ok(("abc", Instruction::Swap)),
@@ -465,7 +936,7 @@ fn parse_instruction_works() {
     );
     assert_eq!(
         Ok(("abc", Instruction::Slide(3))),
-        parse_instruction(" \t\n\t\t\nabc")
+        parse_instruction(" \t\n \t\t\nabc")
     );
 
     // Arithmetic
@@ -501,23 +972,23 @@ fn parse_instruction_works() {
     // …many instructions missing
     assert_eq!(
         Ok(("abc", Instruction::SetLabel("A".to_string()))),
-        parse_instruction("\n  \t     \t\nabc")
+        parse_instruction("\n   \t     \t\nabc")
     );
     assert_eq!(
         Ok(("abc", Instruction::CallSubroutine("A".to_string()))),
-        parse_instruction("\n \t\t     \t\nabc")
+        parse_instruction("\n \t \t     \t\nabc")
     );
     assert_eq!(
         Ok(("abc", Instruction::Jump("A".to_string()))),
-        parse_instruction("\n \n\t     \t\nabc")
+        parse_instruction("\n \n \t     \t\nabc")
     );
     assert_eq!(
         Ok(("abc", Instruction::JZero("A".to_string()))),
-        parse_instruction("\n\t \t     \t\nabc")
+        parse_instruction("\n\t  \t     \t\nabc")
     );
     assert_eq!(
         Ok(("abc", Instruction::JNeg("A".to_string()))),
-        parse_instruction("\n\t\t\t     \t\nabc")
+        parse_instruction("\n\t\t \t     \t\nabc")
     );
 
     assert_eq!(
@@ -551,9 +1022,204 @@ fn parse_instruction_works() {
 #[test]
 fn parse_program_works() {
     // Stack
-    assert_eq!(Ok(vec![Instruction::Push(1)]), parse_program("  \t\n"));
+    assert_eq!(Ok(vec![Instruction::Push(1)]), parse_program("   \t\n"));
     assert_eq!(
         Ok(vec![Instruction::Push(1), Instruction::Duplicate]),
-        parse_program("  \t\n \n ")
+        parse_program("   \t\n \n ")
+    );
+}
+
+#[test]
+fn parse_errors_point_to_the_source() {
+    // the comment before the tabs shifts the columns but is not part of the prefix
+    assert_eq!(
+        Err(ParseError {
+            line: 2,
+            column: 4,
+            offset: 9,
+            prefix: "\t\t".to_string(),
+            expected: vec![' ', '\t'],
+            message: "[lf] is an unexpected character".to_string(),
+        }),
+        parse_program("x   \t\ny\t\t\n")
+    );
+    assert_eq!(
+        Err(ParseError {
+            line: 1,
+            column: 5,
+            offset: 4,
+            prefix: "   \t".to_string(),
+            expected: vec![' ', '\t', '\n'],
+            message: "unexpected end of program while parsing a stack manipulation instruction"
+                .to_string(),
+        }),
+        parse_program("   \t")
+    );
+
+    // columns are counted in characters, offsets in bytes
+    let error = parse_program("é\t\t\n").unwrap_err();
+    assert_eq!((1, 4, 4), (error.line, error.column, error.offset));
+    assert_eq!(
+        "[lf] is an unexpected character at line 1, column 4 after [tab][tab], expected [space] or [tab]",
+        error.to_string()
+    );
+
+    let too_large = format!("  \t{}\n", "\t".repeat(40));
+    let error = parse_program(&too_large).unwrap_err();
+    assert_eq!((2, "  ".to_string()), (error.offset, error.prefix));
+    assert!(error.expected.is_empty());
+}
+
+#[test]
+fn truncated_programs_are_reported() {
+    let complete = [
+        ("   \t\n", "a stack manipulation instruction"),
+        (" \t\n \t\n", "a stack manipulation instruction"),
+        ("\t \t\t", "an arithmetic instruction"),
+        ("\t\t\t", "a heap access instruction"),
+        ("\t\n\t ", "an I/O instruction"),
+        ("\n \t \t\t\t\t\t  \n", "a flow control instruction"),
+        ("\n\n\n", "a flow control instruction"),
+    ];
+    for (program, group) in complete.iter() {
+        assert!(parse_program(program).is_ok());
+        for length in 1..program.len() {
+            let error = parse_program(&program[..length]).unwrap_err();
+            assert_eq!(length, error.offset);
+            // a lone tab could still become one of three kinds of instruction
+            if &program[..length] != "\t" {
+                assert_eq!(
+                    format!("unexpected end of program while parsing {}", group),
+                    error.message
+                );
+            }
+        }
+    }
+    assert_eq!(
+        "unexpected end of program while parsing an arithmetic, heap access or I/O instruction",
+        parse_program("\t").unwrap_err().message
+    );
+}
+
+#[test]
+fn parse_program_spanned_works() {
+    let program = parse_program_spanned(
+        "push1:   \t\nadd:\té   end\n\n\n",
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        vec![
+            SpannedInstruction {
+                instruction: Instruction::Push(1),
+                span: Span {
+                    start: 6,
+                    end: 11,
+                    line: 1,
+                    column: 7
+                },
+            },
+            SpannedInstruction {
+                instruction: Instruction::Add,
+                span: Span {
+                    start: 15,
+                    end: 21,
+                    line: 2,
+                    column: 5
+                },
+            },
+            SpannedInstruction {
+                instruction: Instruction::EndOfProgram,
+                span: Span {
+                    start: 24,
+                    end: 27,
+                    line: 2,
+                    column: 13
+                },
+            },
+        ],
+        program
+    );
+}
+
+#[test]
+fn parse_program_recovering_works() {
+    let too_large = format!("  \t{}\n", "\t".repeat(40));
+    let program = format!(
+        "{}{}{}{}{}",
+        "   \t\n",    // Push(1)
+        "\n\n\t",     // invalid, skipped up to the unexpected tab
+        "\t   ",       // Add
+        too_large,     // skipped as a whole
+        "\t\n \t\n\n" // PrintInt, then a truncated flow control instruction
+    );
+    let (instructions, errors) = parse_program_recovering(&program, &ParseOptions::default());
+
+    let instructions: Vec<Instruction> = instructions.into_iter().map(|s| s.instruction).collect();
+    assert_eq!(
+        vec![Instruction::Push(1), Instruction::Add, Instruction::PrintInt],
+        instructions
     );
-}
\ No newline at end of file
+    let errors: Vec<(usize, String)> = errors.into_iter().map(|e| (e.offset, e.message)).collect();
+    assert_eq!(
+        vec![
+            (7, "[tab] is an unexpected character".to_string()),
+            (14, "The int does not fit in 32 bits".to_string()),
+            (
+                program.len(),
+                "unexpected end of program while parsing a flow control instruction".to_string()
+            ),
+        ],
+        errors
+    );
+}
+
+#[test]
+fn parse_program_recovering_reports_the_end_of_input() {
+    // invalid, skipped up to a mark without the end of its label
+    let program = "\n\n\t\t\n\n  \t";
+    let (instructions, errors) = parse_program_recovering(program, &ParseOptions::default());
+
+    assert!(instructions.is_empty());
+    let errors: Vec<(usize, String)> = errors.into_iter().map(|e| (e.offset, e.message)).collect();
+    assert_eq!(
+        vec![
+            (2, "[tab] is an unexpected character".to_string()),
+            (
+                program.len(),
+                "unexpected end of program while parsing a flow control instruction".to_string()
+            ),
+        ],
+        errors
+    );
+}
+
+#[test]
+fn parse_program_reads_dialects() {
+    let expected = Ok(vec![
+        Instruction::Push(1),
+        Instruction::Add,
+        Instruction::EndOfProgram,
+    ]);
+    let sources = [
+        (Dialect::Crlf, "   \t\r\n\t   \r\n\r\n\n"),
+        (Dialect::Stl, "push1: SSSTL add: TSSS end: LLL"),
+        (Dialect::Abc, "AAABC BAAA CCC"),
+    ];
+    for (dialect, source) in sources.iter() {
+        let options = ParseOptions {
+            dialect: dialect.clone(),
+            ..ParseOptions::default()
+        };
+        assert_eq!(expected, parse_program_with(source, &options), "{}", dialect);
+    }
+
+    let crlf = ParseOptions {
+        dialect: Dialect::Crlf,
+        ..ParseOptions::default()
+    };
+    let program = parse_program_spanned("   \t\r\n\t   ", &crlf).unwrap();
+    assert_eq!(0..6, program[0].span.start..program[0].span.end);
+    assert_eq!(2, program[1].span.line);
+    assert!(parse_program_with("SSSTL", &ParseOptions::default()).unwrap().is_empty());
+}
@@ -0,0 +1,355 @@
+/* The runtime of the programs written by the c_backend module. They behave like the virtual
+ * machine with 32 bits integers, and exit with 3 after a runtime error. MODE, COUNT and
+ * INSTRUCTIONS are defined by the program, before the runtime. */
+#include <ctype.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+enum { CHECKED, WRAPPING, SATURATING };
+
+static int32_t *stack;
+static size_t len, cap;
+/* The heap is split in pages, allocated the first time a cell of theirs is written, so that
+ * any address up to INT32_MAX can be used */
+#define PAGE_BITS 12
+#define PAGE_SIZE ((size_t)1 << PAGE_BITS)
+static int32_t *heap[((size_t)INT32_MAX >> PAGE_BITS) + 1];
+static size_t *calls;
+static size_t calls_len, calls_cap;
+
+/* Reports an error like RuntimeError does, then exits */
+static void fail(size_t ip, const char *format, ...)
+{
+    va_list args;
+    fflush(stdout);
+    va_start(args, format);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    if (ip < COUNT)
+        fprintf(stderr, " (instruction %zu: %s)\n", ip, INSTRUCTIONS[ip]);
+    else
+        fprintf(stderr, " (instruction %zu)\n", ip);
+    exit(3);
+}
+
+static void out_of_memory(void)
+{
+    fputs("out of memory\n", stderr);
+    exit(3);
+}
+
+static void *grow(void *items, size_t *capacity, size_t size)
+{
+    *capacity = *capacity ? *capacity * 2 : 64;
+    items = realloc(items, *capacity * size);
+    if (!items)
+        out_of_memory();
+    return items;
+}
+
+static void need(size_t ip, size_t n)
+{
+    if (len < n)
+        fail(ip, "not enough elements on the stack: %zu needed, %zu available", n, len);
+}
+
+static void push(int32_t v)
+{
+    if (len == cap)
+        stack = grow(stack, &cap, sizeof *stack);
+    stack[len++] = v;
+}
+
+/* The result of an operation, according to the integer mode */
+static int32_t integer(size_t ip, int64_t v)
+{
+    if (v >= INT32_MIN && v <= INT32_MAX)
+        return (int32_t)v;
+    if (MODE == CHECKED)
+        fail(ip, "arithmetic overflow");
+    if (MODE == WRAPPING)
+        return (int32_t)(uint32_t)v;
+    return v < 0 ? INT32_MIN : INT32_MAX;
+}
+
+static void add(size_t ip)
+{
+    need(ip, 2);
+    stack[len - 2] = integer(ip, (int64_t)stack[len - 2] + stack[len - 1]);
+    len--;
+}
+
+static void sub(size_t ip)
+{
+    need(ip, 2);
+    stack[len - 2] = integer(ip, (int64_t)stack[len - 2] - stack[len - 1]);
+    len--;
+}
+
+static void mul(size_t ip)
+{
+    need(ip, 2);
+    stack[len - 2] = integer(ip, (int64_t)stack[len - 2] * stack[len - 1]);
+    len--;
+}
+
+/* Rounds towards negative infinity */
+static void divide(size_t ip)
+{
+    int32_t l, r, q;
+    need(ip, 2);
+    l = stack[len - 2];
+    r = stack[len - 1];
+    if (r == 0)
+        fail(ip, "division by zero");
+    if (r == -1) {
+        q = integer(ip, -(int64_t)l);
+    } else {
+        q = l / r;
+        if (l % r != 0 && (l < 0) != (r < 0))
+            q--;
+    }
+    stack[len - 2] = q;
+    len--;
+}
+
+/* The result has the sign of the divisor */
+static void modulo(size_t ip)
+{
+    int32_t l, r, m;
+    need(ip, 2);
+    l = stack[len - 2];
+    r = stack[len - 1];
+    if (r == 0)
+        fail(ip, "division by zero");
+    m = r == -1 ? 0 : l % r;
+    if (m != 0 && (m < 0) != (r < 0))
+        m += r;
+    stack[len - 2] = m;
+    len--;
+}
+
+static void write_heap(size_t ip, int32_t address, int32_t value)
+{
+    int32_t **page;
+    if (address < 0)
+        fail(ip, "invalid heap address %d", address);
+    page = &heap[address >> PAGE_BITS];
+    if (!*page) {
+        *page = calloc(PAGE_SIZE, sizeof **page);
+        if (!*page)
+            out_of_memory();
+    }
+    (*page)[address & (PAGE_SIZE - 1)] = value;
+}
+
+static void store(size_t ip)
+{
+    need(ip, 2);
+    len -= 2;
+    write_heap(ip, stack[len], stack[len + 1]);
+}
+
+static void retrieve(size_t ip)
+{
+    int32_t address;
+    int32_t *page;
+    need(ip, 1);
+    address = stack[len - 1];
+    if (address < 0)
+        fail(ip, "invalid heap address %d", address);
+    page = heap[address >> PAGE_BITS];
+    stack[len - 1] = page ? page[address & (PAGE_SIZE - 1)] : 0;
+}
+
+static void call(size_t id)
+{
+    if (calls_len == calls_cap)
+        calls = grow(calls, &calls_cap, sizeof *calls);
+    calls[calls_len++] = id;
+}
+
+static void returning(size_t ip)
+{
+    if (calls_len == 0)
+        fail(ip, "can't return from a subroutine: call stack is empty");
+}
+
+static void print_char(size_t ip)
+{
+    int32_t c;
+    need(ip, 1);
+    c = stack[--len];
+    if (c < 0 || c > 0x10FFFF || (c >= 0xD800 && c <= 0xDFFF))
+        fail(ip, "%d is not a valid character", c);
+    if (c < 0x80) {
+        putchar(c);
+    } else if (c < 0x800) {
+        putchar(0xC0 | c >> 6);
+        putchar(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        putchar(0xE0 | c >> 12);
+        putchar(0x80 | (c >> 6 & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    } else {
+        putchar(0xF0 | c >> 18);
+        putchar(0x80 | (c >> 12 & 0x3F));
+        putchar(0x80 | (c >> 6 & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    }
+}
+
+static void print_int(size_t ip)
+{
+    need(ip, 1);
+    printf("%d", stack[--len]);
+}
+
+static int read_byte(size_t ip)
+{
+    int c = getchar();
+    if (c == EOF && ferror(stdin))
+        fail(ip, "unable to read the input");
+    return c;
+}
+
+/* Reads a UTF-8 character */
+static void read_char(size_t ip)
+{
+    static const int32_t smallest[] = {0, 0, 0x80, 0x800, 0x10000};
+    int c, width = 1, i;
+    int32_t v = 0;
+    need(ip, 1);
+    fflush(stdout);
+    c = read_byte(ip);
+    if (c == EOF)
+        fail(ip, "end of input");
+    if (c < 0x80) {
+        width = 1;
+        v = c;
+    } else if (c >= 0xC0 && c <= 0xDF) {
+        width = 2;
+        v = c & 0x1F;
+    } else if (c >= 0xE0 && c <= 0xEF) {
+        width = 3;
+        v = c & 0x0F;
+    } else if (c >= 0xF0 && c <= 0xF7) {
+        width = 4;
+        v = c & 0x07;
+    } else {
+        fail(ip, "input is not valid UTF-8");
+    }
+    for (i = 1; i < width; i++) {
+        c = read_byte(ip);
+        if (c == EOF || (c & 0xC0) != 0x80)
+            fail(ip, "input is not valid UTF-8");
+        v = v << 6 | (c & 0x3F);
+    }
+    if (v < smallest[width] || v > 0x10FFFF || (v >= 0xD800 && v <= 0xDFFF))
+        fail(ip, "input is not valid UTF-8");
+    write_heap(ip, stack[--len], v);
+}
+
+/* Writes the line between double quotes, escaped like rust does */
+static void invalid_integer(size_t ip, const char *line)
+{
+    fflush(stdout);
+    fputc('"', stderr);
+    for (; *line; line++) {
+        unsigned char c = (unsigned char)*line;
+        if (c == '"' || c == '\\')
+            fprintf(stderr, "\\%c", c);
+        else if (c == '\t')
+            fputs("\\t", stderr);
+        else if (c == '\r')
+            fputs("\\r", stderr);
+        else if (c < 0x20 || c == 0x7F)
+            fprintf(stderr, "\\u{%x}", c);
+        else
+            fputc(c, stderr);
+    }
+    fputc('"', stderr);
+    fail(ip, " is not a valid integer");
+}
+
+/* Parses a decimal, 0x or 0o number, with an optional sign, like the virtual machine */
+static int parse_integer(const char *text, int32_t *value)
+{
+    const char *end = text + strlen(text);
+    int negative = 0, radix = 10, digits = 0, big = 0;
+    uint64_t magnitude = 0;
+    uint32_t wrapped = 0;
+    while (isspace((unsigned char)*text))
+        text++;
+    while (end > text && isspace((unsigned char)end[-1]))
+        end--;
+    if (text < end && (*text == '-' || *text == '+'))
+        negative = *text++ == '-';
+    if (end - text >= 2 && text[0] == '0' && (text[1] == 'x' || text[1] == 'X')) {
+        radix = 16;
+        text += 2;
+    } else if (end - text >= 2 && text[0] == '0' && (text[1] == 'o' || text[1] == 'O')) {
+        radix = 8;
+        text += 2;
+    }
+    for (; text < end; text++, digits++) {
+        int c = tolower((unsigned char)*text), digit;
+        if (c >= '0' && c <= '9')
+            digit = c - '0';
+        else if (c >= 'a' && c <= 'z')
+            digit = c - 'a' + 10;
+        else
+            return 0;
+        if (digit >= radix)
+            return 0;
+        magnitude = magnitude * radix + digit;
+        wrapped = wrapped * radix + digit;
+        if (magnitude > UINT32_MAX) {
+            big = 1;
+            magnitude = UINT32_MAX;
+        }
+    }
+    if (digits == 0)
+        return 0;
+    if (!big && magnitude <= (uint64_t)INT32_MAX + negative) {
+        *value = (int32_t)(negative ? -(int64_t)magnitude : (int64_t)magnitude);
+        return 1;
+    }
+    if (MODE == CHECKED)
+        return 0;
+    if (MODE == WRAPPING)
+        *value = (int32_t)(negative ? 0u - wrapped : wrapped);
+    else
+        *value = negative ? INT32_MIN : INT32_MAX;
+    return 1;
+}
+
+static void read_int(size_t ip)
+{
+    char *line = NULL;
+    size_t line_len = 0, line_cap = 0;
+    int c;
+    int32_t v = 0;
+    need(ip, 1);
+    fflush(stdout);
+    for (;;) {
+        c = read_byte(ip);
+        if (line_len + 1 >= line_cap)
+            line = grow(line, &line_cap, 1);
+        if (c == EOF || c == '\n')
+            break;
+        line[line_len++] = (char)c;
+    }
+    if (c == EOF && line_len == 0)
+        fail(ip, "end of input");
+    while (line_len > 0 && line[line_len - 1] == '\r')
+        line_len--;
+    line[line_len] = '\0';
+    if (!parse_integer(line, &v))
+        invalid_integer(ip, line);
+    free(line);
+    write_heap(ip, stack[--len], v);
+}
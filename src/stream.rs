@@ -0,0 +1,274 @@
+use crate::dialect::Dialect;
+use crate::parser::{read_instruction, ParseError, ParseOptions, Span, SpannedInstruction};
+use std::io::BufRead;
+
+// The longest instruction without an argument, every other instruction ends with a \n
+const LONGEST_COMMAND: usize = 4;
+
+/// Parses a program lazily from any `BufRead`, one instruction at a time. Only the
+/// instruction being read is kept in memory, so programs can be read from a pipe or be
+/// larger than the memory. It stops after the first error.
+pub struct InstructionReader<R> {
+    input: R,
+    options: ParseOptions,
+    // the tokens of the instruction being read, where they start and where they end
+    pending: String,
+    positions: Vec<Position>,
+    ends: Vec<usize>,
+    // where the next byte of the input is
+    position: Position,
+    done: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<R: BufRead> InstructionReader<R> {
+    pub fn new(input: R, options: ParseOptions) -> InstructionReader<R> {
+        InstructionReader {
+            input,
+            options,
+            pending: String::new(),
+            positions: Vec::new(),
+            ends: Vec::new(),
+            position: Position {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+            done: false,
+        }
+    }
+
+    // Reads up to the next token, false at the end of the input
+    fn read_token(&mut self) -> Result<bool, ParseError> {
+        loop {
+            let start = self.position;
+            let c = match self.read_char()? {
+                Some(c) => c,
+                None => return Ok(false),
+            };
+            let token = match c {
+                Some('\r') if self.options.dialect == Dialect::Crlf => {
+                    if self.peek_byte()? == Some(b'\n') {
+                        self.read_char()?;
+                        Some('\n')
+                    } else {
+                        None
+                    }
+                }
+                Some(c) => self.options.dialect.token(c),
+                None => None,
+            };
+            if let Some(token) = token {
+                self.pending.push(token);
+                self.positions.push(start);
+                self.ends.push(self.position.offset);
+                return Ok(true);
+            }
+        }
+    }
+
+    fn peek_byte(&mut self) -> Result<Option<u8>, ParseError> {
+        match self.input.fill_buf() {
+            Ok(buffer) => Ok(buffer.first().cloned()),
+            Err(e) => Err(self.error(
+                self.position,
+                Vec::new(),
+                format!("unable to read the program: {}", e),
+            )),
+        }
+    }
+
+    // Reads the next character, None at the end of the input, Some(None) for invalid UTF-8
+    fn read_char(&mut self) -> Result<Option<Option<char>>, ParseError> {
+        let first = match self.peek_byte()? {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+        self.input.consume(1);
+        let width = match first {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        let mut bytes = vec![first];
+        while bytes.len() < width {
+            match self.peek_byte()? {
+                Some(byte) if byte & 0xC0 == 0x80 => {
+                    self.input.consume(1);
+                    bytes.push(byte);
+                }
+                _ => break,
+            }
+        }
+
+        self.position.offset += bytes.len();
+        if first == b'\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Ok(Some(
+            std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next()),
+        ))
+    }
+
+    fn error(&self, position: Position, expected: Vec<char>, message: String) -> ParseError {
+        ParseError {
+            line: position.line,
+            column: position.column,
+            offset: position.offset,
+            prefix: String::new(),
+            expected,
+            message,
+        }
+    }
+
+    fn next_instruction(&mut self) -> Result<Option<SpannedInstruction>, ParseError> {
+        loop {
+            let more = self.read_token()?;
+            if !more && self.pending.is_empty() {
+                return Ok(None);
+            }
+            if more && self.pending.len() > LONGEST_COMMAND && !self.pending.ends_with('\n') {
+                // the instruction can't be complete yet
+                continue;
+            }
+
+            match read_instruction(&self.pending, &self.options) {
+                Ok((_, instruction)) => {
+                    let start = self.positions[0];
+                    let end = self.ends[self.ends.len() - 1];
+                    self.pending.clear();
+                    self.positions.clear();
+                    self.ends.clear();
+                    return Ok(Some(SpannedInstruction {
+                        instruction,
+                        span: Span {
+                            start: start.offset,
+                            end,
+                            line: start.line,
+                            column: start.column,
+                        },
+                    }));
+                }
+                // more characters are needed, unless there are none left
+                Err(ref failure) if failure.end_of_input && more => continue,
+                Err(failure) => {
+                    let position = match self.positions.get(failure.at) {
+                        Some(position) => *position,
+                        None => self.position,
+                    };
+                    let mut error = self.error(position, failure.expected, failure.message);
+                    error.prefix = self.pending[..failure.at].to_string();
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for InstructionReader<R> {
+    type Item = Result<SpannedInstruction, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_instruction();
+        match result {
+            Ok(Some(instruction)) => Some(Ok(instruction)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+#[test]
+fn reads_the_same_instructions_as_parse_program() {
+    use crate::parser::parse_program_spanned;
+    use std::io::BufReader;
+
+    let options = ParseOptions {
+        allow_sign_only_numbers: true,
+        ..ParseOptions::default()
+    };
+    for name in ["calc", "count2", "fact", "hanoi", "hworld2", "name"].iter() {
+        let path = format!("examples/{}.ws", name);
+        let source = std::fs::read_to_string(&path).unwrap();
+        // a tiny buffer makes sure nothing relies on having the whole program at once
+        let file = std::fs::File::open(&path).unwrap();
+        let reader = InstructionReader::new(BufReader::with_capacity(3, file), options.clone());
+        assert_eq!(
+            parse_program_spanned(&source, &options).unwrap(),
+            reader.collect::<Result<Vec<_>, _>>().unwrap(),
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn reports_the_same_errors_as_parse_program() {
+    use crate::parser::parse_program;
+
+    let sources = [
+        "x   \t\ny\t\t\n",
+        "   \t",
+        "é\t\t\n",
+        "\t",
+        "  \t\n",
+        "\n  \t",
+    ];
+    for source in sources.iter() {
+        let mut reader = InstructionReader::new(source.as_bytes(), ParseOptions::default());
+        let error = loop {
+            match reader.next() {
+                Some(Ok(_)) => continue,
+                Some(Err(error)) => break error,
+                None => panic!("{:?} should not parse", source),
+            }
+        };
+        assert_eq!(parse_program(source).unwrap_err(), error, "{:?}", source);
+        assert_eq!(None, reader.next());
+    }
+}
+
+#[test]
+fn reads_every_dialect() {
+    let program = "push1:   \t\nadd:\t   é\n\n\n";
+    for dialect in [
+        Dialect::Crlf,
+        Dialect::Stl,
+        Dialect::Abc,
+        "custom:→↓⏎".parse().unwrap(),
+    ]
+    .iter()
+    {
+        let options = ParseOptions {
+            dialect: dialect.clone(),
+            ..ParseOptions::default()
+        };
+        let source = dialect.encode(program);
+        let streamed: Result<Vec<_>, _> =
+            InstructionReader::new(source.as_bytes(), options.clone()).collect();
+        let parsed = crate::parser::parse_program_spanned(&source, &options);
+        assert_eq!(parsed, streamed, "{}", dialect);
+        assert_eq!(3, parsed.unwrap().len(), "{}", dialect);
+    }
+}
@@ -1,92 +1,427 @@
+use crate::arithmetic::{ArithmeticError, IntegerMode, Number};
+use crate::bignum::BigNum;
+use crate::bytecode::{Bytecode, Op};
 use crate::instruction::*;
+use crate::linker::{self, LinkError};
 use std::collections::HashMap;
-type ExecutionResult<'a> = Result<(), &'a str>;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader, Stdin, Stdout, Write};
+use std::ops::Index;
+type ExecutionResult = Result<(), RuntimeError>;
+// What the individual instructions return, before the error is given its context
+type StepResult = Result<(), RuntimeErrorKind>;
 
-pub struct VirtualMachine {
-    pub stack: Vec<i32>,
-    pub heap: Vec<i32>,
+/// An error that stopped the virtual machine, along with where it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub ip: usize,
+    /// The instruction being executed, None when `ip` is outside of the program
+    pub instruction: Option<Instruction>,
+    pub kind: RuntimeErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    StackUnderflow {
+        needed: usize,
+        available: usize,
+    },
+    /// CopyNth or Slide were given a negative argument
+    NegativeArgument(i64),
+    UnknownLabel(String),
+    CallStackUnderflow,
+    DivisionByZero,
+    /// The result of an arithmetic operation does not fit in an integer
+    ArithmeticOverflow,
+    /// The integer mode is `Promote` but the values are not `Promoted` ones
+    CanNotPromote,
+    InvalidHeapAddress(i64),
+    /// PrintChar was asked to print a value that is not a character
+    InvalidCharacter(i64),
+    InputError(InputError),
+    OutputError(String),
+    /// The program ran past its last instruction without reaching an EndOfProgram
+    InstructionPointerOutOfBounds,
+}
+
+impl From<ArithmeticError> for RuntimeErrorKind {
+    fn from(error: ArithmeticError) -> RuntimeErrorKind {
+        match error {
+            ArithmeticError::DivisionByZero => RuntimeErrorKind::DivisionByZero,
+            ArithmeticError::Overflow => RuntimeErrorKind::ArithmeticOverflow,
+            ArithmeticError::CanNotPromote => RuntimeErrorKind::CanNotPromote,
+        }
+    }
+}
+
+impl From<InputError> for RuntimeErrorKind {
+    fn from(error: InputError) -> RuntimeErrorKind {
+        RuntimeErrorKind::InputError(error)
+    }
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::StackUnderflow { needed, available } => write!(
+                f,
+                "not enough elements on the stack: {} needed, {} available",
+                needed, available
+            ),
+            RuntimeErrorKind::NegativeArgument(v) => write!(f, "negative argument {}", v),
+            RuntimeErrorKind::UnknownLabel(label) => write!(f, "unknown label {:?}", label),
+            RuntimeErrorKind::DivisionByZero => write!(f, "division by zero"),
+            RuntimeErrorKind::ArithmeticOverflow => write!(f, "arithmetic overflow"),
+            RuntimeErrorKind::CanNotPromote => {
+                write!(f, "the promote integer mode needs values that can grow")
+            }
+            RuntimeErrorKind::CallStackUnderflow => {
+                write!(f, "can't return from a subroutine: call stack is empty")
+            }
+            RuntimeErrorKind::InvalidHeapAddress(address) => {
+                write!(f, "invalid heap address {}", address)
+            }
+            RuntimeErrorKind::InvalidCharacter(v) => write!(f, "{} is not a valid character", v),
+            RuntimeErrorKind::InputError(e) => write!(f, "{}", e),
+            RuntimeErrorKind::OutputError(e) => write!(f, "unable to write to the output: {}", e),
+            RuntimeErrorKind::InstructionPointerOutOfBounds => {
+                write!(
+                    f,
+                    "reached the end of the program without an end instruction"
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.instruction {
+            Some(instruction) => write!(
+                f,
+                "{} (instruction {}: {:?})",
+                self.kind, self.ip, instruction
+            ),
+            None => write!(f, "{} (instruction {})", self.kind, self.ip),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+/// Reasons why ReadChar or ReadInt could not get a value from the input
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputError {
+    EndOfInput,
+    /// The line read by ReadInt, as it was typed
+    InvalidInteger(String),
+    InvalidUtf8,
+    Unreadable,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::EndOfInput => write!(f, "end of input"),
+            InputError::InvalidInteger(text) => write!(f, "{:?} is not a valid integer", text),
+            InputError::InvalidUtf8 => write!(f, "input is not valid UTF-8"),
+            InputError::Unreadable => write!(f, "unable to read the input"),
+        }
+    }
+}
+
+/// How characters are read by ReadChar and written by PrintChar
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharEncoding {
+    /// One byte is one character, its value is between 0 and 255
+    Byte,
+    /// Characters are UTF-8 scalar values, like `getChar` in the reference implementation
+    Utf8,
+}
+
+/// What ReadChar does when there is nothing left to read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EofBehaviour {
+    /// Stop the program with an error, like the reference implementation
+    Error,
+    /// Store -1 at the requested address
+    StoreMinusOne,
+    /// Store 0 at the requested address
+    StoreZero,
+}
+
+/// The heap of the virtual machine. Only the cells that were written are kept, so that any
+/// address can be used, and every other cell holds 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heap<N = i32> {
+    cells: HashMap<usize, N>,
+    len: usize,
+    zero: N,
+}
+
+impl<N: Number> Heap<N> {
+    pub fn new() -> Heap<N> {
+        Heap {
+            cells: HashMap::new(),
+            len: 0,
+            zero: N::from_i32(0),
+        }
+    }
+
+    /// One past the largest address written
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn set(&mut self, address: usize, value: N) {
+        self.len = self.len.max(address.saturating_add(1));
+        self.cells.insert(address, value);
+    }
+}
+
+impl<N: Number> Default for Heap<N> {
+    fn default() -> Heap<N> {
+        Heap::new()
+    }
+}
+
+impl<N> Index<usize> for Heap<N> {
+    type Output = N;
+
+    fn index(&self, address: usize) -> &N {
+        self.cells.get(&address).unwrap_or(&self.zero)
+    }
+}
+
+/// The virtual machine reads from `input` (ReadChar, ReadInt) and writes to `output`
+/// (PrintChar, PrintInt). By default those are the process stdin and stdout, but any
+/// `BufRead`/`Write` pair can be used, for instance a byte slice and a `Vec<u8>` in tests.
+/// The stack and the heap hold `i32` values unless another `Number`, like `BigNum`, is chosen.
+pub struct VirtualMachine<R = BufReader<Stdin>, W = Stdout, N = i32> {
+    pub stack: Vec<N>,
+    pub heap: Heap<N>,
     pub call_stack: Vec<usize>,
     pub ip: usize,
-    pub labels: HashMap<String, usize>,
-    pub instructions: Vec<Instruction>,
+    // The three forms of the program are built together when it is loaded, and are only
+    // read afterwards so that they stay the same program
+    instructions: Vec<Instruction>,
+    targets: Vec<Option<usize>>,
+    bytecode: Bytecode,
     pub debug: bool,
+    pub char_encoding: CharEncoding,
+    pub eof_behaviour: EofBehaviour,
+    pub integer_mode: IntegerMode,
+    pub input: R,
+    pub output: W,
 }
 
 impl VirtualMachine {
     pub fn new(instructions: &[Instruction]) -> VirtualMachine {
-        let mut vm = VirtualMachine {
+        VirtualMachine::with_io(
+            instructions,
+            BufReader::new(std::io::stdin()),
+            std::io::stdout(),
+        )
+    }
+}
+
+impl<R: BufRead, W: Write> VirtualMachine<R, W> {
+    pub fn with_io(instructions: &[Instruction], input: R, output: W) -> VirtualMachine<R, W> {
+        VirtualMachine::load(instructions, input, output)
+    }
+}
+
+impl<R: BufRead, W: Write, N: Number> VirtualMachine<R, W, N> {
+    // Like with_io, for any kind of number: VirtualMachine::<_, _, BigNum>::load(...)
+    // Branches to unknown labels only fail when they are executed.
+    pub fn load(instructions: &[Instruction], input: R, output: W) -> VirtualMachine<R, W, N> {
+        let targets = linker::resolve(instructions);
+        VirtualMachine::with_targets(instructions, targets, input, output)
+    }
+
+    // Like load, but fails before running anything when a label is defined twice or when a
+    // branch goes to an unknown label
+    pub fn link(
+        instructions: &[Instruction],
+        input: R,
+        output: W,
+    ) -> Result<VirtualMachine<R, W, N>, LinkError> {
+        let targets = linker::link(instructions)?;
+        Ok(VirtualMachine::with_targets(
+            instructions,
+            targets,
+            input,
+            output,
+        ))
+    }
+
+    fn with_targets(
+        instructions: &[Instruction],
+        targets: Vec<Option<usize>>,
+        input: R,
+        output: W,
+    ) -> VirtualMachine<R, W, N> {
+        VirtualMachine {
             stack: Vec::with_capacity(64),
             call_stack: Vec::with_capacity(64),
-            heap: Vec::new(),
+            heap: Heap::new(),
             ip: 0,
-            labels: HashMap::new(),
             instructions: instructions.to_vec(),
+            bytecode: Bytecode::compile(instructions, &targets),
+            targets,
             debug: false,
-        };
-
-        for (index, instruction) in instructions.iter().enumerate() {
-            if let Instruction::SetLabel(label) = instruction {
-                vm.labels.insert(label.to_string(), index);
-            }
+            char_encoding: CharEncoding::Utf8,
+            eof_behaviour: EofBehaviour::Error,
+            integer_mode: IntegerMode::default(),
+            input,
+            output,
         }
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Where each call or jump goes, resolved once when the program is loaded
+    pub fn targets(&self) -> &[Option<usize>] {
+        &self.targets
+    }
 
-        vm
+    /// The instructions lowered for `run_program`, compiled when the program is loaded
+    pub fn bytecode(&self) -> &Bytecode {
+        &self.bytecode
     }
 
-    // Run n instructions and returns the last execution
+    // Runs n instructions, and stops at the first one that fails
     pub fn run_n(&mut self, n: usize) -> ExecutionResult {
-        for _ in 0..(n - 1) {
+        for _ in 0..n {
             if self.debug {
-                println!("{:?}", self.instructions[self.ip]);
-                println!("\t{:?}", self.stack);
-                println!("\t{:?}", self.ip);
+                eprintln!("{:?}", self.instructions.get(self.ip));
+                eprintln!("\t{:?}", self.stack);
+                eprintln!("\t{:?}", self.ip);
             }
-            let _ = self.run_instruction();
+            self.run_instruction()?;
             if self.debug {
-                println!("\t{:?}", self.stack);
-                println!("\t{:?}", self.ip);
+                eprintln!("\t{:?}", self.stack);
+                eprintln!("\t{:?}", self.ip);
             }
         }
-        if self.debug {
-            println!("{:?}", self.instructions[self.ip]);
-            println!("\t{:?}", self.stack);
-            println!("\t{:?}", self.ip);
-        }
-        self.run_instruction()
+        Ok(())
     }
 
-    pub fn run_program(&mut self) {
-        // println!("Labels:");
-        // println!("{:?}", self.labels);
+    // Refuses to run at all in the Promote mode when the numbers can not be promoted, instead
+    // of failing on the first operation
+    pub(crate) fn check_integer_mode(&self) -> ExecutionResult {
+        if self.integer_mode == IntegerMode::Promote && !N::can_promote() {
+            return Err(RuntimeError {
+                ip: self.ip,
+                instruction: None,
+                kind: RuntimeErrorKind::CanNotPromote,
+            });
+        }
+        Ok(())
+    }
 
-        if self.instructions.is_empty() { return; }
+    pub fn run_program(&mut self) -> ExecutionResult {
+        self.check_integer_mode()?;
+        if self.instructions.is_empty() {
+            return Ok(());
+        }
+        if !self.debug {
+            let result = self.run_bytecode();
+            let _ = self.output.flush();
+            return result;
+        }
 
-        while self.instructions[self.ip] != Instruction::EndOfProgram {
+        let mut result = Ok(());
+        while self.instructions.get(self.ip) != Some(&Instruction::EndOfProgram) {
             if self.debug {
-                println!("{:?}", self.instructions[self.ip]);
-                println!("\t{:?}", self.stack);
-                println!("\t{:?}", self.ip);
+                eprintln!("{:?}", self.instructions.get(self.ip));
+                eprintln!("\t{:?}", self.stack);
+                eprintln!("\t{:?}", self.ip);
             }
-            if let Err(ref e) = self.run_instruction() {
-                println!("{:?}", e);
+            result = self.run_instruction();
+            if result.is_err() {
                 break;
             }
             if self.debug {
-                println!("\t{:?}", self.stack);
-                println!("\t{:?}", self.ip);
+                eprintln!("\t{:?}", self.stack);
+                eprintln!("\t{:?}", self.ip);
+            }
+        }
+        let _ = self.output.flush();
+        result
+    }
+
+    // Runs up to the end of the program, like run_program without the debug output but with
+    // a faster dispatch: the ops are copied instead of cloning the instructions
+    fn run_bytecode(&mut self) -> ExecutionResult {
+        while self.step_bytecode()? {}
+        Ok(())
+    }
+
+    // Runs the op at ip, false when it is the end of the program
+    pub(crate) fn step_bytecode(&mut self) -> Result<bool, RuntimeError> {
+        let ip = self.ip;
+        let result = match self.bytecode.ops.get(ip) {
+            Some(Op::End) => return Ok(false),
+            Some(op) => self.execute_op(*op),
+            None => {
+                return Err(RuntimeError {
+                    ip,
+                    instruction: None,
+                    kind: RuntimeErrorKind::InstructionPointerOutOfBounds,
+                })
             }
+        };
+        match result {
+            Ok(()) => Ok(true),
+            Err(kind) => Err(RuntimeError {
+                ip,
+                instruction: self.instructions.get(ip).cloned(),
+                kind,
+            }),
         }
     }
 
     pub fn run_instruction(&mut self) -> ExecutionResult {
-        let instruction = self.instructions[self.ip].clone();
+        self.check_integer_mode()?;
+        let ip = self.ip;
+        let instruction = match self.instructions.get(ip) {
+            Some(instruction) => instruction.clone(),
+            None => {
+                return Err(RuntimeError {
+                    ip,
+                    instruction: None,
+                    kind: RuntimeErrorKind::InstructionPointerOutOfBounds,
+                })
+            }
+        };
+        let target = self.targets.get(ip).cloned().flatten();
+        self.execute(&instruction, target)
+            .map_err(|kind| RuntimeError {
+                ip,
+                instruction: Some(instruction),
+                kind,
+            })
+    }
+
+    // The target is where the instruction branches to, if it does
+    fn execute(&mut self, instruction: &Instruction, target: Option<usize>) -> StepResult {
         match instruction {
-            Instruction::Push(v) => self.run_push(v),
+            Instruction::Push(v) => self.run_push(N::from_i32(*v)),
+            Instruction::PushBig(v) => self.run_push(N::from_bignum(v, self.integer_mode)?),
             Instruction::Duplicate => self.run_duplicate(),
-            Instruction::CopyNth(v) => self.run_copynth(v),
+            Instruction::CopyNth(v) => self.run_copynth(*v),
             Instruction::Swap => self.run_swap(),
             Instruction::Discard => self.run_discard(),
-            Instruction::Slide(v) => self.run_slide(v),
+            Instruction::Slide(v) => self.run_slide(*v),
             Instruction::Add => self.run_add(),
             Instruction::Sub => self.run_sub(),
             Instruction::Mul => self.run_mul(),
@@ -95,10 +430,10 @@ impl VirtualMachine {
             Instruction::Store => self.run_store(),
             Instruction::Retrieve => self.run_retrieve(),
             Instruction::SetLabel(_) => self.move_on(),
-            Instruction::CallSubroutine(subroutine) => self.run_callsubroutine(&subroutine.clone()),
-            Instruction::Jump(label) => self.run_jump(&label.clone()),
-            Instruction::JZero(label) => self.run_jzero(&label.clone()),
-            Instruction::JNeg(label) => self.run_jneg(&label.clone()),
+            Instruction::CallSubroutine(_) => self.run_callsubroutine(target),
+            Instruction::Jump(_) => self.run_jump(target),
+            Instruction::JZero(_) => self.run_jzero(target),
+            Instruction::JNeg(_) => self.run_jneg(target),
             Instruction::EndOfSubroutine => self.run_endofsubroutine(),
             Instruction::EndOfProgram => Ok(()),
             Instruction::PrintChar => self.run_printchar(),
@@ -108,195 +443,396 @@ impl VirtualMachine {
         }
     }
 
-    fn move_on(&mut self) -> ExecutionResult {
+    fn execute_op(&mut self, op: Op) -> StepResult {
+        match op {
+            Op::Push(v) => self.run_push(N::from_i32(v)),
+            Op::PushConst(index) => {
+                let value = N::from_bignum(&self.bytecode.constants[index], self.integer_mode)?;
+                self.run_push(value)
+            }
+            Op::Duplicate => self.run_duplicate(),
+            Op::CopyNth(v) => self.run_copynth(v),
+            Op::Swap => self.run_swap(),
+            Op::Discard => self.run_discard(),
+            Op::Slide(v) => self.run_slide(v),
+            Op::Add => self.run_add(),
+            Op::Sub => self.run_sub(),
+            Op::Mul => self.run_mul(),
+            Op::Div => self.run_div(),
+            Op::Mod => self.run_mod(),
+            Op::Store => self.run_store(),
+            Op::Retrieve => self.run_retrieve(),
+            Op::Nop => self.move_on(),
+            Op::Call(target) => self.run_callsubroutine(target),
+            Op::Jump(target) => self.run_jump(target),
+            Op::JZero(target) => self.run_jzero(target),
+            Op::JNeg(target) => self.run_jneg(target),
+            Op::Return => self.run_endofsubroutine(),
+            Op::End => Ok(()),
+            Op::PrintChar => self.run_printchar(),
+            Op::PrintInt => self.run_printint(),
+            Op::ReadChar => self.run_readchar(),
+            Op::ReadInt => self.run_readint(),
+        }
+    }
+
+    fn move_on(&mut self) -> StepResult {
         self.ip += 1;
         Ok(())
     }
 
-    fn run_push(&mut self, v: i32) -> ExecutionResult {
+    fn run_push(&mut self, v: N) -> StepResult {
         self.stack.push(v);
         self.move_on()
     }
 
-    fn run_duplicate(&mut self) -> ExecutionResult {
+    fn run_duplicate(&mut self) -> StepResult {
         if !self.stack.is_empty() {
-            self.stack.push(self.stack[self.stack.len() - 1]);
+            self.stack.push(self.stack[self.stack.len() - 1].clone());
             self.move_on()
         } else {
-            Err("Can't duplicate: stack is empty")
+            Err(self.underflow(1))
         }
     }
 
-    fn run_copynth(&mut self, _v: i32) -> ExecutionResult {
-        unimplemented!("Todo !")
+    // Copies the nth element (0 being the top of the stack) on top of the stack
+    fn run_copynth(&mut self, v: i32) -> StepResult {
+        if v < 0 {
+            return Err(RuntimeErrorKind::NegativeArgument(i64::from(v)));
+        }
+        let len = self.stack.len();
+        let n = v as usize;
+        if n < len {
+            self.stack.push(self.stack[len - 1 - n].clone());
+            self.move_on()
+        } else {
+            Err(self.underflow(n + 1))
+        }
     }
-    fn run_swap(&mut self) -> ExecutionResult {
+    fn run_swap(&mut self) -> StepResult {
         let len = self.stack.len();
         if len >= 2 {
             self.stack.swap(len - 1, len - 2);
             self.move_on()
         } else {
-            Err("Can't swap: not enough elements on the stack")
+            Err(self.underflow(2))
         }
     }
-    fn run_discard(&mut self) -> ExecutionResult {
+    fn run_discard(&mut self) -> StepResult {
         if !self.stack.is_empty() {
             let _ = self.stack.pop();
             self.move_on()
         } else {
-            Err("Can't discard: stack is empty")
+            Err(self.underflow(1))
         }
     }
-    fn run_slide(&mut self, _v: i32) -> ExecutionResult {
-        unimplemented!("Todo !")
+    // Removes v elements below the top of the stack, keeping the top one
+    fn run_slide(&mut self, v: i32) -> StepResult {
+        if v < 0 {
+            return Err(RuntimeErrorKind::NegativeArgument(i64::from(v)));
+        }
+        let len = self.stack.len();
+        let n = v as usize;
+        if n < len {
+            let top = self.stack.pop().unwrap();
+            self.stack.truncate(len - 1 - n);
+            self.stack.push(top);
+            self.move_on()
+        } else {
+            Err(self.underflow(n + 1))
+        }
     }
 
-    fn run_stack_instruction_2_params(&mut self, f: impl Fn(i32, i32) -> i32) -> ExecutionResult {
+    // The operands are only removed from the stack when the operation succeeds
+    fn run_stack_instruction_2_params(
+        &mut self,
+        f: impl Fn(&N, &N, IntegerMode) -> Result<N, ArithmeticError>,
+    ) -> StepResult {
         let len = self.stack.len();
         if len >= 2 {
-            let right = self.stack.pop().unwrap();
-            let left = self.stack.pop().unwrap();
-            self.stack.push(f(left, right));
+            let result = f(
+                &self.stack[len - 2],
+                &self.stack[len - 1],
+                self.integer_mode,
+            )?;
+            self.stack.truncate(len - 2);
+            self.stack.push(result);
             self.move_on()
         } else {
-            Err("Can't perform operation: not enough elements on the stack")
+            Err(self.underflow(2))
         }
     }
 
-    fn run_add(&mut self) -> ExecutionResult {
-        self.run_stack_instruction_2_params(|l, r| l + r)
+    fn run_add(&mut self) -> StepResult {
+        self.run_stack_instruction_2_params(N::add)
     }
-    fn run_sub(&mut self) -> ExecutionResult {
-        self.run_stack_instruction_2_params(|l, r| l - r)
+    fn run_sub(&mut self) -> StepResult {
+        self.run_stack_instruction_2_params(N::sub)
     }
-    fn run_mul(&mut self) -> ExecutionResult {
-        self.run_stack_instruction_2_params(|l, r| l * r)
+    fn run_mul(&mut self) -> StepResult {
+        self.run_stack_instruction_2_params(N::mul)
     }
-    fn run_div(&mut self) -> ExecutionResult {
-        self.run_stack_instruction_2_params(|l, r| l / r)
+    fn run_div(&mut self) -> StepResult {
+        self.run_stack_instruction_2_params(N::div)
     }
-    fn run_mod(&mut self) -> ExecutionResult {
-        self.run_stack_instruction_2_params(|l, r| l % r)
+    fn run_mod(&mut self) -> StepResult {
+        self.run_stack_instruction_2_params(|l, r, _| l.modulo(r))
     }
-    fn run_store(&mut self) -> ExecutionResult {
+    fn run_store(&mut self) -> StepResult {
         let len = self.stack.len();
         if len >= 2 {
             let value = self.stack.pop().unwrap();
             let address = self.stack.pop().unwrap();
 
-            self.write_heap(address, value);
+            self.write_heap(address, value)?;
             self.move_on()
         } else {
-            Err("Can't perform operation: not enough elements on the stack")
+            Err(self.underflow(2))
         }
     }
-    fn run_retrieve(&mut self) -> ExecutionResult {
+    fn run_retrieve(&mut self) -> StepResult {
         if !self.stack.is_empty() {
             let address = self.stack.pop().unwrap();
-            let value = self.read_heap(address);
+            let value = self.read_heap(address)?;
             self.stack.push(value);
             self.move_on()
         } else {
-            Err("Can't retrieve value: not enough elements on the stack")
+            Err(self.underflow(1))
         }
     }
 
-    fn run_callsubroutine(&mut self, label: &str) -> ExecutionResult {
+    fn run_callsubroutine(&mut self, target: Option<usize>) -> StepResult {
         self.call_stack.push(self.ip);
-        self.run_jump(label)
+        self.run_jump(target)
     }
 
-    fn run_jump(&mut self, label: &str) -> ExecutionResult {
-        match self.labels.get(label) {
+    // The target is None when the label of the branch is unknown
+    fn run_jump(&mut self, target: Option<usize>) -> StepResult {
+        match target {
             Some(new_ip) => {
-                self.ip = *new_ip;
+                self.ip = new_ip;
                 Ok(())
             }
-            None => Err("Can't jump: missing label !"),
+            None => {
+                let instruction = self.instructions.get(self.ip);
+                let label = instruction
+                    .and_then(linker::branch_label)
+                    .unwrap_or_default();
+                Err(RuntimeErrorKind::UnknownLabel(label.to_string()))
+            }
         }
     }
 
     fn conditionnal_jump(
         &mut self,
-        label: &str,
-        predicate: impl Fn(i32) -> bool,
-    ) -> ExecutionResult {
+        target: Option<usize>,
+        predicate: impl Fn(&N) -> bool,
+    ) -> StepResult {
         if !self.stack.is_empty() {
             let top_stack_value = self.stack.pop().unwrap();
-            if predicate(top_stack_value) {
-                self.run_jump(label)
+            if predicate(&top_stack_value) {
+                self.run_jump(target)
             } else {
                 self.move_on()
             }
         } else {
-            Err("Can't jump: stack is empty")
+            Err(self.underflow(1))
         }
     }
 
-    fn run_jzero(&mut self, label: &str) -> ExecutionResult {
-        self.conditionnal_jump(label, |t| t == 0)
+    fn run_jzero(&mut self, target: Option<usize>) -> StepResult {
+        self.conditionnal_jump(target, |t| t.is_zero())
     }
-    fn run_jneg(&mut self, label: &str) -> ExecutionResult {
-        self.conditionnal_jump(label, |t| t < 0)
+    fn run_jneg(&mut self, target: Option<usize>) -> StepResult {
+        self.conditionnal_jump(target, |t| t.is_negative())
     }
-    fn run_endofsubroutine(&mut self) -> ExecutionResult {
+    fn run_endofsubroutine(&mut self) -> StepResult {
         if !self.call_stack.is_empty() {
             let previous_address = self.call_stack.pop().unwrap();
             self.ip = previous_address;
             self.move_on()
         } else {
-            Err("Can't jump back to previous address: call stack is empty")
+            Err(RuntimeErrorKind::CallStackUnderflow)
         }
     }
 
-    fn run_printchar(&mut self) -> ExecutionResult {
+    fn run_printchar(&mut self) -> StepResult {
         if !self.stack.is_empty() {
-            let top_stack_value = self.stack.pop().unwrap();
-            print!("{}", (top_stack_value as u8) as char);
+            let code = saturating_i64(&self.stack.pop().unwrap());
+            let written = match self.char_encoding {
+                CharEncoding::Byte => match u8::try_from(code) {
+                    Ok(byte) => self.output.write_all(&[byte]),
+                    Err(_) => return Err(RuntimeErrorKind::InvalidCharacter(code)),
+                },
+                CharEncoding::Utf8 => {
+                    match u32::try_from(code).ok().and_then(std::char::from_u32) {
+                        Some(c) => write!(self.output, "{}", c),
+                        None => return Err(RuntimeErrorKind::InvalidCharacter(code)),
+                    }
+                }
+            };
+            written.map_err(|e| RuntimeErrorKind::OutputError(e.to_string()))?;
             self.move_on()
         } else {
-            Err("Can't print: stack is empty")
+            Err(self.underflow(1))
         }
     }
-    fn run_printint(&mut self) -> ExecutionResult {
+    fn run_printint(&mut self) -> StepResult {
         if !self.stack.is_empty() {
             let top_stack_value = self.stack.pop().unwrap();
-            print!("{}", top_stack_value);
+            write!(self.output, "{}", top_stack_value)
+                .map_err(|e| RuntimeErrorKind::OutputError(e.to_string()))?;
             self.move_on()
         } else {
-            Err("Can't print: stack is empty")
+            Err(self.underflow(1))
         }
     }
-    fn run_readchar(&mut self) -> ExecutionResult {
-        unimplemented!("Todo !")
+    fn run_readchar(&mut self) -> StepResult {
+        if !self.stack.is_empty() {
+            let _ = self.output.flush();
+            let value = match self.read_char()? {
+                Some(c) => c,
+                None => match self.eof_behaviour {
+                    EofBehaviour::Error => return Err(InputError::EndOfInput.into()),
+                    EofBehaviour::StoreMinusOne => -1,
+                    EofBehaviour::StoreZero => 0,
+                },
+            };
+
+            let address = self.stack.pop().unwrap();
+            self.write_heap(address, N::from_i32(value))?;
+            self.move_on()
+        } else {
+            Err(self.underflow(1))
+        }
     }
-    fn run_readint(&mut self) -> ExecutionResult {
+    fn run_readint(&mut self) -> StepResult {
         if !self.stack.is_empty() {
+            // make sure a prompt written before is visible before blocking on the input
+            let _ = self.output.flush();
             let mut input = String::new();
-            std::io::stdin().read_line(&mut input).unwrap();
-
-            println!("{:?}", input);
-            let int_read: i32 = input.trim().parse().unwrap();
-            println!("{:?}", int_read);
+            match self.input.read_line(&mut input) {
+                Ok(0) => return Err(InputError::EndOfInput.into()),
+                Ok(_) => {}
+                Err(_) => return Err(InputError::Unreadable.into()),
+            }
+            let line = input.trim_end_matches(['\n', '\r']);
+            let invalid_integer = || InputError::InvalidInteger(line.to_string());
+            let int_read = match parse_integer(line) {
+                Some(v) => N::from_bignum(&v, self.integer_mode).map_err(|_| invalid_integer())?,
+                None => return Err(invalid_integer().into()),
+            };
 
             let address = self.stack.pop().unwrap();
-            self.write_heap(address, int_read);
+            self.write_heap(address, int_read)?;
             self.move_on()
         } else {
-            Err("Can't print: stack is empty")
+            Err(self.underflow(1))
         }
     }
 
-    fn write_heap(&mut self, address: i32, value: i32) {
-        self.heap.resize((address + 1) as usize, 0);
-        self.heap[address as usize] = value;
+    // Reads one character according to the encoding, None when the input is exhausted
+    fn read_char(&mut self) -> Result<Option<i32>, InputError> {
+        let first = match self.read_byte()? {
+            Some(b) => b,
+            None => return Ok(None),
+        };
+        if self.char_encoding == CharEncoding::Byte || first < 0x80 {
+            return Ok(Some(i32::from(first)));
+        }
+
+        let width = match first {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return Err(InputError::InvalidUtf8),
+        };
+        let mut bytes = vec![first];
+        for _ in 1..width {
+            match self.read_byte()? {
+                Some(b) => bytes.push(b),
+                None => return Err(InputError::InvalidUtf8),
+            }
+        }
+        match std::str::from_utf8(&bytes) {
+            Ok(s) => Ok(s.chars().next().map(|c| c as i32)),
+            Err(_) => Err(InputError::InvalidUtf8),
+        }
     }
 
-    fn read_heap(&mut self, address: i32) -> i32 {
-        if (address as usize) < self.heap.len() {
-            self.heap[address as usize]
-        } else {
-            0
+    fn read_byte(&mut self) -> Result<Option<u8>, InputError> {
+        let byte = match self.input.fill_buf() {
+            Ok(buffer) => buffer.first().cloned(),
+            Err(_) => return Err(InputError::Unreadable),
+        };
+        if byte.is_some() {
+            self.input.consume(1);
         }
+        Ok(byte)
+    }
+
+    fn underflow(&self, needed: usize) -> RuntimeErrorKind {
+        RuntimeErrorKind::StackUnderflow {
+            needed,
+            available: self.stack.len(),
+        }
+    }
+
+    fn write_heap(&mut self, address: N, value: N) -> StepResult {
+        let address = heap_index(&address)?;
+        self.heap.set(address, value);
+        Ok(())
+    }
+
+    fn read_heap(&mut self, address: N) -> Result<N, RuntimeErrorKind> {
+        let address = heap_index(&address)?;
+        Ok(self.heap[address].clone())
+    }
+}
+
+fn heap_index<N: Number>(address: &N) -> Result<usize, RuntimeErrorKind> {
+    match address.to_i64().map(usize::try_from) {
+        Some(Ok(index)) => Ok(index),
+        _ => Err(RuntimeErrorKind::InvalidHeapAddress(saturating_i64(
+            address,
+        ))),
+    }
+}
+
+// The value as an i64 for error reports, clamped when it is too big
+fn saturating_i64<N: Number>(value: &N) -> i64 {
+    match value.to_i64() {
+        Some(v) => v,
+        None if value.is_negative() => i64::MIN,
+        None => i64::MAX,
+    }
+}
+
+// Parses a number the way ReadInt expects it: surrounding whitespace, an optional sign,
+// then decimal digits or a 0x (hexadecimal) / 0o (octal) literal like Haskell's `read`
+fn parse_integer(text: &str) -> Option<BigNum> {
+    let text = text.trim();
+    let (negative, unsigned) = match text.chars().next() {
+        Some('-') => (true, &text[1..]),
+        Some('+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let (radix, digits) = if unsigned.starts_with("0x") || unsigned.starts_with("0X") {
+        (16, &unsigned[2..])
+    } else if unsigned.starts_with("0o") || unsigned.starts_with("0O") {
+        (8, &unsigned[2..])
+    } else {
+        (10, unsigned)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut value = BigNum::zero();
+    for c in digits.chars() {
+        let digit = c.to_digit(radix)?;
+        value = value
+            .mul(&BigNum::from(radix as i32))
+            .add(&BigNum::from(digit as i32));
     }
+    Some(if negative { value.neg() } else { value })
 }
@@ -0,0 +1,102 @@
+extern crate whitespace;
+
+mod common;
+
+use common::*;
+use std::path::PathBuf;
+use std::process::Command;
+use whitespace::arithmetic::IntegerMode;
+use whitespace::assembler::assemble;
+use whitespace::c_backend::generate_c;
+use whitespace::instruction::Instruction;
+
+// Compiles the program with cc, named after the test that builds it
+fn build(
+    directory: &TempDir,
+    name: &str,
+    instructions: &[Instruction],
+    mode: IntegerMode,
+) -> PathBuf {
+    let source = directory.join(&format!("{}.c", name));
+    let executable = directory.join(name);
+    std::fs::write(&source, generate_c(instructions, mode).unwrap()).unwrap();
+    let status = Command::new("cc")
+        .arg("-o")
+        .arg(&executable)
+        .arg(&source)
+        .status()
+        .unwrap();
+    assert!(status.success(), "{:?}", instructions);
+    executable
+}
+
+#[test]
+fn compiled_examples_run_like_the_vm_test() {
+    if !has_command("cc", "compiled_examples_run_like_the_vm_test") {
+        return;
+    }
+    let directory = TempDir::new("c_backend_examples");
+    for (name, inputs) in EXAMPLES.iter() {
+        let instructions = example(name);
+        let executable = build(&directory, name, &instructions, IntegerMode::Checked);
+        for input in inputs.iter() {
+            assert_runs_like_the_vm(&executable, &instructions, input, IntegerMode::Checked);
+        }
+    }
+}
+
+#[test]
+fn compiled_programs_fail_like_the_vm_test() {
+    if !has_command("cc", "compiled_programs_fail_like_the_vm_test") {
+        return;
+    }
+    let directory = TempDir::new("c_backend_programs");
+    let programs = [
+        "",
+        "add",
+        "push 1\n dup\n mul\n swap\n drop\n drop",
+        "push 7\n push 0\n div",
+        "push 7\n push 0\n mod",
+        "push -2147483648\n push -1\n div\n printi\n end",
+        "push -2147483648\n push -1\n mod\n printi\n end",
+        "push 2147483647\n push 1\n add\n printi\n end",
+        "push -2147483648\n push 1\n sub\n printi\n end",
+        "push 65536\n dup\n mul\n printi\n end",
+        "push 4294967296\n printi\n end",
+        "push -7\n push 2\n div\n printi\n push -7\n push 2\n mod\n printi\n \
+         push 7\n push -2\n mod\n printi\n end",
+        "push 1\n push 2\n push 3\n copy 2\n slide 1\n printi\n printi\n printi\n end",
+        "push 1\n copy 1",
+        "push 1\n copy -1",
+        "push 1\n slide 1",
+        "push 1\n slide -1",
+        "push -1\n push 5\n store",
+        "push -1\n retrieve",
+        "push 100\n retrieve\n printi\n push 3\n push 4\n store\n push 3\n retrieve\n printi\n end",
+        "push 2147483647\n push 7\n store\n push 2147483647\n retrieve\n printi\n end",
+        "push 1114112\n printc",
+        "push 55296\n printc",
+        "push 233\n printc\n push 128512\n printc\n end",
+        "ret",
+        "call f\n end\n f: call g\n push 1\n printi\n ret\n g: push 2\n printi\n ret",
+        "push 1\n jz a\n push 0\n jz a\n a: push -1\n jn b\n end\n b: push 9\n printi",
+        "push 0\n readc\n push 0\n retrieve\n printi\n end",
+        "push 0\n readi\n push 0\n retrieve\n printi\n end",
+    ];
+    for (index, program) in programs.iter().enumerate() {
+        let instructions = assemble(program).unwrap();
+        for mode in [
+            IntegerMode::Checked,
+            IntegerMode::Wrapping,
+            IntegerMode::Saturating,
+        ]
+        .iter()
+        {
+            let name = format!("program_{}_{:?}", index, mode);
+            let executable = build(&directory, &name, &instructions, *mode);
+            for input in INPUTS.iter() {
+                assert_runs_like_the_vm(&executable, &instructions, input, *mode);
+            }
+        }
+    }
+}
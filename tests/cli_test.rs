@@ -0,0 +1,101 @@
+use std::process::{Command, Output};
+
+fn ws(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ws"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn it_runs_programs() {
+    let output = ws(&["run", "examples/hworld.ws"]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        "Hello, world of spaces!\r\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let jit = ws(&["run", "--jit", "examples/hworld.ws"]);
+    assert_eq!(Some(0), jit.status.code());
+    assert_eq!(output.stdout, jit.stdout);
+}
+
+#[test]
+fn it_exits_with_distinct_codes() {
+    assert_eq!(Some(1), ws(&["run", "examples/missing.ws"]).status.code());
+    // hworld2.ws has numbers made of a sign only
+    assert_eq!(Some(2), ws(&["run", "examples/hworld2.ws"]).status.code());
+    assert_eq!(Some(2), ws(&["check", "examples/hworld2.ws"]).status.code());
+    assert_eq!(
+        Some(0),
+        ws(&["check", "--allow-sign-only", "examples/hworld2.ws"])
+            .status
+            .code()
+    );
+    // a jump to a label that does not exist is reported before anything runs
+    let path = std::env::temp_dir().join("ws_cli_test_unknown_label.ws");
+    std::fs::write(&path, "   \t\n\n\t \t\n\t   \n\n\n").unwrap();
+    let output = ws(&["run", path.to_str().unwrap()]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown label"));
+    // so is a label defined twice
+    let path = std::env::temp_dir().join("ws_cli_test_duplicate_label.ws");
+    std::fs::write(&path, "\n  \t\n\n  \t\n\n\n\n").unwrap();
+    for command in ["run", "check"].iter() {
+        let output = ws(&[command, path.to_str().unwrap()]);
+        assert_eq!(Some(2), output.status.code());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("already defined"));
+    }
+
+    // name.ws reads a name, there is nothing to read
+    let output = ws(&["run", "--input", "/dev/null", "examples/name.ws"]);
+    assert_eq!(Some(3), output.status.code());
+}
+
+#[test]
+fn it_assembles_and_disassembles() {
+    let assembled = ws(&["asm", "--dialect", "stl", "examples/count2.wsa"]);
+    assert_eq!(Some(0), assembled.status.code());
+    let formatted = ws(&["fmt", "--to", "stl", "examples/count2.ws"]);
+    assert_eq!(assembled.stdout, formatted.stdout);
+
+    let disassembled = ws(&["disasm", "examples/count2.ws"]);
+    assert!(String::from_utf8_lossy(&disassembled.stdout).starts_with("    push 1\nC:\n"));
+    // only the subcommands that run the program read an input
+    let with_input = ws(&["disasm", "--input", "/dev/null", "examples/count2.ws"]);
+    assert_ne!(Some(0), with_input.status.code());
+}
+
+#[test]
+fn it_builds_executables() {
+    let source = ws(&["build", "--emit-c", "examples/hworld.ws"]);
+    assert_eq!(Some(0), source.status.code());
+    assert!(String::from_utf8_lossy(&source.stdout).contains("int main(void)"));
+    assert_eq!(
+        Some(4),
+        ws(&["build", "--bignum", "examples/hworld.ws"]).status.code()
+    );
+
+    if std::process::Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let executable = std::env::temp_dir().join("ws_cli_test_hworld");
+    let path = executable.to_str().unwrap();
+    assert_eq!(
+        Some(0),
+        ws(&["build", "examples/hworld.ws", "-o", path]).status.code()
+    );
+    let output = std::process::Command::new(path).output().unwrap();
+    assert_eq!(ws(&["run", "examples/hworld.ws"]).stdout, output.stdout);
+}
+
+#[test]
+fn it_compiles_standalone_programs() {
+    let output = ws(&["compile", "--standalone", "examples/hworld.ws"]);
+    assert_eq!(Some(0), output.status.code());
+    let source = String::from_utf8_lossy(&output.stdout);
+    assert!(source.contains("loop {\n        match block {\n"));
+    assert!(!source.contains("whitespace::"));
+}
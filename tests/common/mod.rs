@@ -0,0 +1,129 @@
+// What the tests that run programs in other ways than the virtual machine have in common.
+// Not every test uses all of it.
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use whitespace::arithmetic::IntegerMode;
+use whitespace::instruction::Instruction;
+use whitespace::parser::{parse_program_with, ParseOptions};
+use whitespace::vm::*;
+
+/// The examples, with the inputs they are run with
+pub const EXAMPLES: &[(&str, &[&str])] = &[
+    ("count", &[""]),
+    ("count2", &[""]),
+    ("hworld", &[""]),
+    ("hworld2", &[""]),
+    ("name", &["Ada\n", "Zoë\n", ""]),
+    ("fact", &["12\n", "13\n", "0x0a\n", "x\n", ""]),
+    ("loctest", &["3\n4\n", "3\n"]),
+    ("hanoi", &["4\n"]),
+    ("calc", &["5\n3\n-1\n", "5\nx\n", "2147483647\n1\n-1\n"]),
+];
+
+/// Inputs that ReadChar and ReadInt do not all accept
+pub const INPUTS: &[&str] = &[
+    "",
+    "é",
+    "\u{ff}",
+    " +0x7fffffff \r\n",
+    "-0o17\n",
+    "9999999999\n",
+    "\"1\\\t2\n",
+];
+
+/// The instructions of an example. hworld2 pushes numbers made of a sign only.
+pub fn example(name: &str) -> Vec<Instruction> {
+    let lenient = ParseOptions {
+        allow_sign_only_numbers: true,
+        ..ParseOptions::default()
+    };
+    let contents = std::fs::read_to_string(format!("examples/{}.ws", name)).unwrap();
+    parse_program_with(&contents, &lenient).unwrap()
+}
+
+/// Whether the command can be run. When it can not, the test calling it says that it is
+/// skipped, in a way that `cargo test` does not hide.
+pub fn has_command(command: &str, test: &str) -> bool {
+    let found = Command::new(command).arg("--version").output().is_ok();
+    if !found {
+        let _ = writeln!(
+            std::io::stderr(),
+            "skipping {}: {} was not found",
+            test,
+            command
+        );
+    }
+    found
+}
+
+/// A directory of the system temporary directory, removed with everything in it when the
+/// value is dropped
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("ws_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn join(&self, file: &str) -> PathBuf {
+        self.path.join(file)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Runs the executable and the virtual machine with the same input, they have to write the
+/// same output, report the same error and exit with 3 after it
+pub fn assert_runs_like_the_vm(
+    executable: &Path,
+    instructions: &[Instruction],
+    input: &str,
+    mode: IntegerMode,
+) {
+    let mut child = Command::new(executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the program may stop before reading everything
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output().unwrap();
+
+    let mut vm = VirtualMachine::with_io(instructions, input.as_bytes(), Vec::new());
+    vm.integer_mode = mode;
+    let result = vm.run_program();
+    let context = format!("{:?} {:?}", instructions, input);
+    assert_eq!(
+        String::from_utf8_lossy(&vm.output),
+        String::from_utf8_lossy(&output.stdout),
+        "{}",
+        context
+    );
+    match result {
+        Ok(()) => {
+            assert_eq!(Some(0), output.status.code(), "{}", context);
+            assert!(output.stderr.is_empty(), "{}", context);
+        }
+        Err(e) => {
+            assert_eq!(Some(3), output.status.code(), "{}", context);
+            assert_eq!(
+                format!("{}\n", e),
+                String::from_utf8_lossy(&output.stderr),
+                "{}",
+                context
+            );
+        }
+    }
+}
@@ -0,0 +1,157 @@
+#![cfg(feature = "jit")]
+extern crate whitespace;
+
+mod common;
+
+use whitespace::arithmetic::IntegerMode;
+use whitespace::assembler::assemble;
+use whitespace::instruction::*;
+use whitespace::vm::*;
+
+// Runs a program with the JIT and with run_program, and checks they end the same way
+fn assert_runs_like_the_vm(instructions: &[Instruction], input: &str, mode: IntegerMode) {
+    let mut jit = VirtualMachine::with_io(instructions, input.as_bytes(), Vec::new());
+    let mut vm = VirtualMachine::with_io(instructions, input.as_bytes(), Vec::new());
+    jit.integer_mode = mode;
+    vm.integer_mode = mode;
+    assert_eq!(vm.run_program(), jit.run_jit(), "{:?}", instructions);
+    assert_eq!(vm.output, jit.output, "{:?}", instructions);
+    assert_eq!(vm.stack, jit.stack, "{:?}", instructions);
+    assert_eq!(vm.heap, jit.heap, "{:?}", instructions);
+    assert_eq!(vm.ip, jit.ip, "{:?}", instructions);
+}
+
+#[test]
+fn jit_runs_every_example_test() {
+    for (name, inputs) in common::EXAMPLES.iter() {
+        let instructions = common::example(name);
+        for input in inputs.iter() {
+            assert_runs_like_the_vm(&instructions, input, IntegerMode::Checked);
+        }
+    }
+}
+
+#[test]
+fn jit_grows_the_stack_test() {
+    let instructions = assemble(
+        "    push 10000
+         loop:
+             dup
+             push 1
+             sub
+             dup
+             jz done
+             jmp loop
+         done:
+             end",
+    )
+    .unwrap();
+    assert_runs_like_the_vm(&instructions, "", IntegerMode::Checked);
+}
+
+#[test]
+fn jit_reports_errors_like_the_vm_test() {
+    let programs = [
+        "add",
+        "push 1\n dup\n mul\n swap\n drop\n drop",
+        "push 7\n push 0\n div",
+        "push -2147483648\n push -1\n div",
+        "push -2147483648\n push -1\n mod",
+        "push 2147483647\n push 1\n add",
+        "push -7\n push 2\n div\n push -7\n push 2\n mod\n push 7\n push -2\n mod\n end",
+        "push 1\n copy 1",
+        "push 1\n copy -1",
+        "push 1\n slide 1",
+        "push 1\n slide -1",
+        "push 0\n jz nowhere",
+        "jn nowhere",
+        "push 1\n printi",
+    ];
+    for program in programs.iter() {
+        let instructions = assemble(program).unwrap();
+        for mode in [
+            IntegerMode::Checked,
+            IntegerMode::Wrapping,
+            IntegerMode::Saturating,
+        ]
+        .iter()
+        {
+            assert_runs_like_the_vm(&instructions, "", *mode);
+        }
+    }
+}
+
+#[test]
+fn jit_falls_back_when_debugging_test() {
+    let instructions = assemble("push 1\n push 2\n add\n end").unwrap();
+    let mut vm = VirtualMachine::with_io(&instructions, &b""[..], Vec::new());
+    vm.debug = true;
+    assert_eq!(Ok(()), vm.run_jit());
+    assert_eq!(vec![3], vm.stack);
+}
+
+use proptest::prelude::*;
+
+fn value() -> impl Strategy<Value = i32> {
+    prop_oneof![-3..4i32, any::<i32>()]
+}
+
+fn mode() -> impl Strategy<Value = IntegerMode> {
+    prop_oneof![
+        Just(IntegerMode::Checked),
+        Just(IntegerMode::Wrapping),
+        Just(IntegerMode::Saturating),
+    ]
+}
+
+// Blocks of instructions, each starting with the label of its index. Jumps only go forward,
+// to a later block or to the end, so every program stops.
+fn program() -> impl Strategy<Value = Vec<Instruction>> {
+    let block = prop::collection::vec((0..19u8, value(), any::<usize>()), 0..10);
+    (prop::collection::vec(block, 1..6), any::<bool>()).prop_map(|(blocks, end)| {
+        let count = blocks.len();
+        let mut instructions = Vec::new();
+        for (index, block) in blocks.into_iter().enumerate() {
+            instructions.push(Instruction::SetLabel(index.to_string()));
+            for (kind, v, target) in block {
+                let label = (index + 1 + target % (count - index)).to_string();
+                instructions.extend(match kind {
+                    0..=2 => vec![Instruction::Push(v)],
+                    3 => vec![Instruction::Duplicate],
+                    4 => vec![Instruction::CopyNth(v)],
+                    5 => vec![Instruction::Swap],
+                    6 => vec![Instruction::Discard],
+                    7 => vec![Instruction::Slide(v)],
+                    8 => vec![Instruction::Add],
+                    9 => vec![Instruction::Sub],
+                    10 => vec![Instruction::Mul],
+                    11 => vec![Instruction::Div],
+                    12 => vec![Instruction::Mod],
+                    13 => vec![Instruction::Jump(label)],
+                    14 => vec![Instruction::JZero(label)],
+                    15 => vec![Instruction::JNeg(label)],
+                    16 => vec![Instruction::PrintInt],
+                    // heap addresses stay small, so that retrieves read what was stored
+                    17 => vec![
+                        Instruction::Push(v.rem_euclid(8)),
+                        Instruction::Swap,
+                        Instruction::Store,
+                    ],
+                    _ => vec![Instruction::Push(v.rem_euclid(8)), Instruction::Retrieve],
+                });
+            }
+        }
+        instructions.push(Instruction::SetLabel(count.to_string()));
+        if end {
+            instructions.push(Instruction::EndOfProgram);
+        }
+        instructions
+    })
+}
+
+proptest! {
+    #[test]
+    fn jit_runs_any_program_like_the_vm(instructions in program(), mode in mode()) {
+        assert_runs_like_the_vm(&instructions, "", mode);
+    }
+}
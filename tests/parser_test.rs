@@ -34,6 +34,15 @@ fn it_can_run_count() {
     assert_eq!(tokens, Ok(expected_tokens));
 }
 
+#[test]
+fn it_assembles_count() {
+    use whitespace::assembler::assemble;
+
+    let source = std::fs::read_to_string("examples/count2.wsa").unwrap();
+    let expected = std::fs::read_to_string("examples/count2.ws").unwrap();
+    assert_eq!(Ok(parse_program(&expected).unwrap()), assemble(&source));
+}
+
 
 
 
@@ -64,8 +73,27 @@ proptest! {
         let _ = match_string(&s);
     }
 
-    // #[test]
-    // fn it_parses_anything_without_crashes(s in ".*") {
-    //     let _ = parse_program(&s);
-    // }
+    #[test]
+    fn it_parses_anything_without_crashes(s in ".*") {
+        let _ = parse_program(&s);
+    }
+
+    #[test]
+    fn it_parses_any_regular_program_without_crashes(s in "[ \t\na]*") {
+        let _ = parse_program(&s);
+    }
+
+    #[test]
+    fn it_recovers_from_anything_without_crashes(s in "[ \t\na]*") {
+        let _ = parse_program_recovering(&s, &ParseOptions::default());
+    }
+
+    #[test]
+    fn it_streams_like_it_parses(s in "[ \t\naé]*") {
+        use whitespace::stream::InstructionReader;
+
+        let options = ParseOptions::default();
+        let streamed: Result<Vec<_>, _> = InstructionReader::new(s.as_bytes(), options.clone()).collect();
+        prop_assert_eq!(parse_program_spanned(&s, &options), streamed);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+extern crate whitespace;
+
+mod common;
+
+use common::*;
+use std::path::PathBuf;
+use std::process::Command;
+use whitespace::arithmetic::IntegerMode;
+use whitespace::assembler::assemble;
+use whitespace::compiler::generate_standalone_source_code;
+use whitespace::instruction::Instruction;
+
+// Compiles the program with rustc, without the whitespace crate
+fn build(
+    directory: &TempDir,
+    name: &str,
+    instructions: &[Instruction],
+    mode: IntegerMode,
+) -> PathBuf {
+    let source = directory.join(&format!("{}.rs", name));
+    let executable = directory.join(name);
+    let code = generate_standalone_source_code(instructions, mode).unwrap();
+    std::fs::write(&source, code).unwrap();
+    let output = Command::new("rustc")
+        .arg("-o")
+        .arg(&executable)
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", instructions);
+    // the generated code has to compile without warnings
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    executable
+}
+
+#[test]
+fn standalone_examples_run_like_the_vm_test() {
+    if !has_command("rustc", "standalone_examples_run_like_the_vm_test") {
+        return;
+    }
+    let directory = TempDir::new("standalone_examples");
+    for (name, inputs) in EXAMPLES.iter() {
+        let instructions = example(name);
+        let executable = build(&directory, name, &instructions, IntegerMode::Checked);
+        for input in inputs.iter() {
+            assert_runs_like_the_vm(&executable, &instructions, input, IntegerMode::Checked);
+        }
+    }
+}
+
+#[test]
+fn standalone_programs_fail_like_the_vm_test() {
+    if !has_command("rustc", "standalone_programs_fail_like_the_vm_test") {
+        return;
+    }
+    let directory = TempDir::new("standalone_programs");
+    let programs = [
+        "",
+        "end",
+        "add",
+        "push 7\n push 0\n mod",
+        "push 1\n push 2\n push 3\n copy 2\n slide 1\n printi\n printi\n printi\n end",
+        "push 1\n copy 1",
+        "push 1\n slide -1",
+        "push -1\n push 5\n store",
+        "push 100\n retrieve\n printi\n push 3\n push 4\n store\n push 3\n retrieve\n printi\n end",
+        "push 2147483647\n push 7\n store\n push 2147483647\n retrieve\n printi\n end",
+        "push 55296\n printc",
+        "push 233\n printc\n push 128512\n printc\n end",
+        "ret",
+        "call f\n end\n f: call g\n push 1\n printi\n ret\n g: push 2\n printi\n ret",
+        "push 1\n jz a\n push 0\n jz a\n a: push -1\n jn b\n end\n b: push 9\n printi",
+        "push 0\n readc\n push 0\n retrieve\n printi\n end",
+    ];
+    // the programs whose result depends on the integer mode
+    let overflows = [
+        "push -2147483648\n push -1\n div\n printi\n push -7\n push 2\n div\n printi\n end",
+        "push 2147483647\n push 1\n add\n printi\n end",
+        "push 65536\n dup\n mul\n printi\n end",
+        "push 4294967296\n printi\n end",
+        "push 0\n readi\n push 0\n retrieve\n printi\n end",
+    ];
+    let runs = programs
+        .iter()
+        .map(|program| (program, IntegerMode::Checked))
+        .chain(overflows.iter().flat_map(|program| {
+            vec![
+                (program, IntegerMode::Checked),
+                (program, IntegerMode::Wrapping),
+                (program, IntegerMode::Saturating),
+            ]
+        }));
+    for (index, (program, mode)) in runs.enumerate() {
+        let instructions = assemble(program).unwrap();
+        let name = format!("program_{}", index);
+        let executable = build(&directory, &name, &instructions, mode);
+        for input in INPUTS.iter() {
+            assert_runs_like_the_vm(&executable, &instructions, input, mode);
+        }
+    }
+}
@@ -1,12 +1,16 @@
 extern crate whitespace;
 
-use std::collections::HashMap;
+mod common;
+
+use whitespace::bignum::BigNum;
 use whitespace::instruction::*;
+use whitespace::linker::{LinkError, LinkErrorKind};
+use whitespace::parser::ParseOptions;
 use whitespace::vm::*;
 
 #[test]
 fn instruction_push_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Push(42)]);
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42)]);
     let _ = vm.run_instruction();
     assert_eq!(vec![42], vm.stack);
     assert_eq!(1, vm.ip);
@@ -14,7 +18,7 @@ fn instruction_push_nominal() {
 
 #[test]
 fn instruction_duplicate_nominal_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(17),
         Instruction::Duplicate,
@@ -27,14 +31,14 @@ fn instruction_duplicate_nominal_nominal() {
 
 #[test]
 fn instruction_duplicate_error_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Duplicate]);
+    let mut vm = VirtualMachine::new(&[Instruction::Duplicate]);
     let res = vm.run_instruction();
     assert!(res.is_err());
 }
 
 #[test]
 fn instruction_swap_nominal_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(17),
         Instruction::Swap,
@@ -47,14 +51,14 @@ fn instruction_swap_nominal_nominal() {
 
 #[test]
 fn instruction_swap_error_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Push(42), Instruction::Swap]);
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::Swap]);
     let res = vm.run_n(2);
     assert!(res.is_err());
 }
 
 #[test]
 fn instruction_discard_nominal_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(17),
         Instruction::Discard,
@@ -67,14 +71,14 @@ fn instruction_discard_nominal_nominal() {
 
 #[test]
 fn instruction_discard_error_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Swap]);
+    let mut vm = VirtualMachine::new(&[Instruction::Swap]);
     let res = vm.run_instruction();
     assert!(res.is_err());
 }
 
 #[test]
 fn instruction_add_nominal_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(17),
         Instruction::Add,
@@ -87,7 +91,7 @@ fn instruction_add_nominal_nominal() {
 
 #[test]
 fn instruction_add_error_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Push(42), Instruction::Add]);
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::Add]);
     let _ = vm.run_instruction();
     let res = vm.run_instruction();
     assert!(res.is_err());
@@ -95,7 +99,7 @@ fn instruction_add_error_nominal() {
 
 #[test]
 fn instruction_sub_nominal_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(17),
         Instruction::Sub,
@@ -108,14 +112,14 @@ fn instruction_sub_nominal_nominal() {
 
 #[test]
 fn instruction_sub_error_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Push(42), Instruction::Sub]);
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::Sub]);
     let res = vm.run_n(2);
     assert!(res.is_err());
 }
 
 #[test]
 fn instruction_mul_nominal_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(3),
         Instruction::Push(2),
         Instruction::Mul,
@@ -128,14 +132,14 @@ fn instruction_mul_nominal_nominal() {
 
 #[test]
 fn instruction_mul_error_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Push(42), Instruction::Swap]);
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::Swap]);
     let res = vm.run_n(2);
     assert!(res.is_err());
 }
 
 #[test]
 fn instruction_div_nominal_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(6),
         Instruction::Push(3),
         Instruction::Div,
@@ -148,14 +152,14 @@ fn instruction_div_nominal_nominal() {
 
 #[test]
 fn instruction_div_error_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Push(42), Instruction::Swap]);
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::Swap]);
     let res = vm.run_n(2);
     assert!(res.is_err());
 }
 
 #[test]
 fn instruction_mod_nominal_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(7),
         Instruction::Push(3),
         Instruction::Mod,
@@ -169,28 +173,26 @@ fn instruction_mod_nominal_nominal() {
 
 #[test]
 fn instruction_mod_error_nominal() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Push(42), Instruction::Swap]);
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::Swap]);
     let res = vm.run_n(2);
     assert!(res.is_err());
 }
 
 #[test]
 fn instruction_setlabel_nominal() {
-    let vm = VirtualMachine::new(&vec![
+    let vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(0),
         Instruction::Jump("plop".to_string()),
     ]);
 
-    let mut expected_map: HashMap<String, usize> = HashMap::new();
-    expected_map.insert("plop".to_string(), 1);
-    assert_eq!(vm.labels, expected_map);
+    assert_eq!(vm.targets(), &[None, None, None, Some(1)]);
 }
 
 #[test]
 fn instruction_jump_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(0),
@@ -205,7 +207,7 @@ fn instruction_jump_nominal() {
 
 #[test]
 fn instruction_jump_no_zero_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(0),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(42),
@@ -220,7 +222,7 @@ fn instruction_jump_no_zero_test() {
 
 #[test]
 fn instruction_jump_missing_label_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(0),
         Instruction::Jump("plop".to_string()),
@@ -231,9 +233,32 @@ fn instruction_jump_missing_label_test() {
     assert_eq!(vm.stack, vec![42, 0]);
 }
 
+#[test]
+fn link_reports_missing_labels_before_running() {
+    let program = [
+        Instruction::Push(42),
+        Instruction::PrintInt,
+        Instruction::Jump("plop".to_string()),
+    ];
+    let output: Vec<u8> = Vec::new();
+    let error = VirtualMachine::<_, _, i32>::link(&program, &b""[..], output).err();
+    assert_eq!(
+        Some(LinkError {
+            ip: 2,
+            kind: LinkErrorKind::UnknownLabel("plop".to_string())
+        }),
+        error
+    );
+
+    let mut vm = VirtualMachine::<_, _, i32>::link(&program[..2], &b""[..], Vec::new()).unwrap();
+    assert_eq!(&[None, None], vm.targets());
+    assert!(vm.run_n(2).is_ok());
+    assert_eq!(b"42".to_vec(), vm.output);
+}
+
 #[test]
 fn instruction_jump_empty_stack_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::SetLabel("plop".to_string()),
         Instruction::Jump("plop".to_string()),
     ]);
@@ -246,7 +271,7 @@ fn instruction_jump_empty_stack_test() {
 
 #[test]
 fn instruction_jzero_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(0),
@@ -261,7 +286,7 @@ fn instruction_jzero_nominal() {
 
 #[test]
 fn instruction_jzero_no_jump_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(0),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(42),
@@ -276,7 +301,7 @@ fn instruction_jzero_no_jump_test() {
 
 #[test]
 fn instruction_jzero_missing_label_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(0),
         Instruction::JZero("plop".to_string()),
@@ -289,7 +314,7 @@ fn instruction_jzero_missing_label_test() {
 
 #[test]
 fn instruction_jzero_empty_stack_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::SetLabel("plop".to_string()),
         Instruction::JZero("plop".to_string()),
     ]);
@@ -301,7 +326,7 @@ fn instruction_jzero_empty_stack_test() {
 
 #[test]
 fn instruction_jneg_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(-2),
@@ -316,7 +341,7 @@ fn instruction_jneg_nominal() {
 
 #[test]
 fn instruction_jneg_no_jump_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(-2),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(42),
@@ -331,7 +356,7 @@ fn instruction_jneg_no_jump_test() {
 
 #[test]
 fn instruction_jneg_missing_label_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(-2),
         Instruction::JNeg("plop".to_string()),
@@ -344,7 +369,7 @@ fn instruction_jneg_missing_label_test() {
 
 #[test]
 fn instruction_jneg_empty_stack_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::SetLabel("plop".to_string()),
         Instruction::JNeg("plop".to_string()),
     ]);
@@ -356,7 +381,7 @@ fn instruction_jneg_empty_stack_test() {
 
 #[test]
 fn instruction_call_subroutine_nominal() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(-2),
@@ -372,7 +397,7 @@ fn instruction_call_subroutine_nominal() {
 
 #[test]
 fn instruction_call_subroutine_no_jump_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(-2),
         Instruction::SetLabel("plop".to_string()),
         Instruction::Push(42),
@@ -388,7 +413,7 @@ fn instruction_call_subroutine_no_jump_test() {
 
 #[test]
 fn instruction_call_subroutine_missing_label_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(-2),
         Instruction::CallSubroutine("plop".to_string()),
@@ -402,7 +427,7 @@ fn instruction_call_subroutine_missing_label_test() {
 
 #[test]
 fn instruction_call_subroutine_empty_stack_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::SetLabel("plop".to_string()),
         Instruction::CallSubroutine("plop".to_string()),
     ]);
@@ -415,7 +440,7 @@ fn instruction_call_subroutine_empty_stack_test() {
 
 #[test]
 fn instruction_store_nominal_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(-2),
         Instruction::Store,
@@ -429,7 +454,7 @@ fn instruction_store_nominal_test() {
 
 #[test]
 fn instruction_store_can_resize_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(-2),
         Instruction::Store,
@@ -447,7 +472,7 @@ fn instruction_store_can_resize_test() {
 
 #[test]
 fn instruction_store_not_enough_args_test() {
-    let mut vm = VirtualMachine::new(&vec![Instruction::Push(42), Instruction::Store]);
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::Store]);
     let res = vm.run_n(3);
 
     assert!(res.is_err());
@@ -455,7 +480,7 @@ fn instruction_store_not_enough_args_test() {
 
 #[test]
 fn instruction_retrieve_nominal_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(-2),
         Instruction::Store,
@@ -472,7 +497,7 @@ fn instruction_retrieve_nominal_test() {
 
 #[test]
 fn instruction_retrieve_missing_address_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(128),
         Instruction::Push(-2),
         Instruction::Store,
@@ -490,7 +515,7 @@ fn instruction_retrieve_missing_address_test() {
 
 #[test]
 fn instruction_retrieve_address_outside_bounds_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(-2),
         Instruction::Store,
@@ -507,7 +532,7 @@ fn instruction_retrieve_address_outside_bounds_test() {
 
 #[test]
 fn instruction_retrieve_not_enough_args_test() {
-    let mut vm = VirtualMachine::new(&vec![
+    let mut vm = VirtualMachine::new(&[
         Instruction::Push(42),
         Instruction::Push(17),
         Instruction::Store,
@@ -522,6 +547,761 @@ fn instruction_retrieve_not_enough_args_test() {
 
 #[test]
 fn running_an_empty_program_does_not_crash_test() {
-    let mut vm = VirtualMachine::new(&vec![]);
-    vm.run_program();
+    let mut vm = VirtualMachine::new(&[]);
+    assert!(vm.run_program().is_ok());
+}
+
+#[test]
+fn instruction_printchar_writes_to_output_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[
+            Instruction::Push(105),
+            Instruction::Push(72),
+            Instruction::PrintChar,
+            Instruction::PrintChar,
+        ],
+        std::io::empty(),
+        Vec::new(),
+    );
+    let res = vm.run_n(4);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.output, b"Hi");
+    assert_eq!(vm.stack, vec![]);
+}
+
+#[test]
+fn instruction_printint_writes_to_output_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[Instruction::Push(-42), Instruction::PrintInt],
+        std::io::empty(),
+        Vec::new(),
+    );
+    let res = vm.run_n(2);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.output, b"-42");
+}
+
+#[test]
+fn instruction_readint_reads_from_input_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[Instruction::Push(3), Instruction::ReadInt],
+        "17\n".as_bytes(),
+        Vec::new(),
+    );
+    let res = vm.run_n(2);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.heap[3], 17);
+}
+
+#[test]
+fn instruction_readchar_reads_from_input_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[
+            Instruction::Push(3),
+            Instruction::ReadChar,
+            Instruction::Push(4),
+            Instruction::ReadChar,
+        ],
+        "aé".as_bytes(),
+        Vec::new(),
+    );
+    let res = vm.run_n(4);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.stack, vec![]);
+    assert_eq!(vm.heap[3], 'a' as i32);
+    assert_eq!(vm.heap[4], 'é' as i32);
+}
+
+#[test]
+fn instruction_readchar_byte_encoding_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[
+            Instruction::Push(3),
+            Instruction::ReadChar,
+            Instruction::Push(4),
+            Instruction::ReadChar,
+        ],
+        "é".as_bytes(),
+        Vec::new(),
+    );
+    vm.char_encoding = CharEncoding::Byte;
+    let res = vm.run_n(4);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.heap[3], 0xC3);
+    assert_eq!(vm.heap[4], 0xA9);
+}
+
+#[test]
+fn instruction_readchar_invalid_utf8_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[Instruction::Push(3), Instruction::ReadChar],
+        &[0xC3, b'a'][..],
+        Vec::new(),
+    );
+    let res = vm.run_n(2);
+
+    assert!(res.is_err());
+}
+
+#[test]
+fn instruction_readchar_end_of_input_test() {
+    let instructions = [Instruction::Push(3), Instruction::ReadChar];
+
+    let mut vm = VirtualMachine::with_io(&instructions, std::io::empty(), Vec::new());
+    assert!(vm.run_n(2).is_err());
+
+    let mut vm = VirtualMachine::with_io(&instructions, std::io::empty(), Vec::new());
+    vm.eof_behaviour = EofBehaviour::StoreMinusOne;
+    assert!(vm.run_n(2).is_ok());
+    assert_eq!(vm.heap[3], -1);
+
+    let mut vm = VirtualMachine::with_io(&instructions, std::io::empty(), Vec::new());
+    vm.heap.set(3, 7);
+    vm.eof_behaviour = EofBehaviour::StoreZero;
+    assert!(vm.run_n(2).is_ok());
+    assert_eq!(vm.heap[3], 0);
+}
+
+#[test]
+fn instruction_readchar_empty_stack_test() {
+    let mut vm = VirtualMachine::with_io(&[Instruction::ReadChar], "a".as_bytes(), Vec::new());
+    let res = vm.run_instruction();
+
+    assert!(res.is_err());
+}
+
+#[test]
+fn instruction_printchar_byte_encoding_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[Instruction::Push(0xE9), Instruction::PrintChar],
+        std::io::empty(),
+        Vec::new(),
+    );
+    let res = vm.run_n(2);
+    assert!(res.is_ok());
+    assert_eq!(vm.output, "é".as_bytes());
+
+    let mut vm = VirtualMachine::with_io(
+        &[Instruction::Push(0xE9), Instruction::PrintChar],
+        std::io::empty(),
+        Vec::new(),
+    );
+    vm.char_encoding = CharEncoding::Byte;
+    let res = vm.run_n(2);
+    assert!(res.is_ok());
+    assert_eq!(vm.output, vec![0xE9]);
+
+    for value in [300, -1].iter() {
+        let mut vm = VirtualMachine::with_io(
+            &[Instruction::Push(*value), Instruction::PrintChar],
+            std::io::empty(),
+            Vec::new(),
+        );
+        vm.char_encoding = CharEncoding::Byte;
+        assert_eq!(
+            RuntimeErrorKind::InvalidCharacter(*value as i64),
+            vm.run_n(2).unwrap_err().kind
+        );
+        assert!(vm.output.is_empty());
+    }
+}
+
+#[test]
+fn instruction_readint_accepts_literal_forms_test() {
+    let inputs = [
+        ("  -12 \n", -12),
+        ("+7\n", 7),
+        ("42", 42),
+        ("0x1F\r\n", 31),
+        ("-0o17\n", -15),
+    ];
+    for (input, expected) in inputs.iter() {
+        let mut vm = VirtualMachine::with_io(
+            &[Instruction::Push(0), Instruction::ReadInt],
+            input.as_bytes(),
+            Vec::new(),
+        );
+        let res = vm.run_n(2);
+
+        assert!(res.is_ok());
+        assert_eq!(vm.heap[0], *expected);
+        assert!(vm.output.is_empty());
+    }
+}
+
+#[test]
+fn instruction_readint_invalid_input_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[Instruction::Push(0), Instruction::ReadInt],
+        "4 2\n".as_bytes(),
+        Vec::new(),
+    );
+    let res = vm.run_n(2);
+
+    let error = res.unwrap_err();
+    assert_eq!(
+        error.kind,
+        RuntimeErrorKind::InputError(InputError::InvalidInteger("4 2".to_string()))
+    );
+    assert_eq!(error.ip, 1);
+    assert_eq!(error.instruction, Some(Instruction::ReadInt));
+    assert_eq!(vm.stack, vec![0]);
+}
+
+#[test]
+fn instruction_readint_end_of_input_test() {
+    let mut vm = VirtualMachine::with_io(
+        &[Instruction::Push(0), Instruction::ReadInt],
+        std::io::empty(),
+        Vec::new(),
+    );
+    let res = vm.run_n(2);
+
+    assert_eq!(
+        res.unwrap_err().kind,
+        RuntimeErrorKind::InputError(InputError::EndOfInput)
+    );
+}
+
+#[test]
+fn instruction_copynth_nominal_test() {
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(42),
+        Instruction::Push(17),
+        Instruction::Push(3),
+        Instruction::CopyNth(2),
+        Instruction::CopyNth(0),
+    ]);
+    let res = vm.run_n(5);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.stack, vec![42, 17, 3, 42, 42]);
+    assert_eq!(5, vm.ip);
+}
+
+#[test]
+fn instruction_copynth_out_of_range_test() {
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(42),
+        Instruction::Push(17),
+        Instruction::CopyNth(2),
+    ]);
+    let res = vm.run_n(3);
+
+    assert!(res.is_err());
+    assert_eq!(vm.stack, vec![42, 17]);
+    assert_eq!(2, vm.ip);
+}
+
+#[test]
+fn instruction_copynth_negative_test() {
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::CopyNth(-1)]);
+    let res = vm.run_n(2);
+
+    assert!(res.is_err());
+    assert_eq!(vm.stack, vec![42]);
+}
+
+#[test]
+fn instruction_slide_nominal_test() {
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(42),
+        Instruction::Push(17),
+        Instruction::Push(3),
+        Instruction::Push(5),
+        Instruction::Slide(2),
+    ]);
+    let res = vm.run_n(5);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.stack, vec![42, 5]);
+    assert_eq!(5, vm.ip);
+}
+
+#[test]
+fn instruction_slide_zero_test() {
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(42),
+        Instruction::Push(17),
+        Instruction::Slide(0),
+    ]);
+    let res = vm.run_n(3);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.stack, vec![42, 17]);
+}
+
+#[test]
+fn instruction_slide_out_of_range_test() {
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(42),
+        Instruction::Push(17),
+        Instruction::Slide(2),
+    ]);
+    let res = vm.run_n(3);
+
+    assert!(res.is_err());
+    assert_eq!(vm.stack, vec![42, 17]);
+}
+
+#[test]
+fn instruction_slide_negative_test() {
+    let mut vm = VirtualMachine::new(&[Instruction::Push(42), Instruction::Slide(-1)]);
+    let res = vm.run_n(2);
+
+    assert!(res.is_err());
+    assert_eq!(vm.stack, vec![42]);
+}
+
+#[test]
+fn instruction_store_does_not_shrink_the_heap_test() {
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(128),
+        Instruction::Push(17),
+        Instruction::Store,
+        Instruction::Push(42),
+        Instruction::Push(-2),
+        Instruction::Store,
+    ]);
+    let res = vm.run_n(6);
+
+    assert!(res.is_ok());
+    assert_eq!(vm.heap[42], -2);
+    assert_eq!(vm.heap[128], 17);
+}
+
+#[test]
+fn instruction_store_negative_address_test() {
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(-1),
+        Instruction::Push(17),
+        Instruction::Store,
+    ]);
+    let res = vm.run_n(3);
+
+    assert!(res.is_err());
+}
+
+fn run_example(path: &str, input: &str) -> String {
+    run_example_with(path, input, &ParseOptions::default())
+}
+
+fn run_example_with(path: &str, input: &str, options: &ParseOptions) -> String {
+    let contents = std::fs::read_to_string(path).unwrap();
+    let instructions = whitespace::parser::parse_program_with(&contents, options).unwrap();
+    let mut vm = VirtualMachine::with_io(&instructions, input.as_bytes(), Vec::new());
+    let _ = vm.run_program();
+    String::from_utf8(vm.output).unwrap()
+}
+
+#[test]
+fn it_runs_every_example_test() {
+    assert_eq!(run_example("examples/count.ws", ""), "");
+    assert_eq!(
+        run_example("examples/count2.ws", ""),
+        "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n"
+    );
+    assert_eq!(
+        run_example("examples/hworld.ws", ""),
+        "Hello, world of spaces!\r\n"
+    );
+    // hworld2 pushes numbers made of a sign only
+    let lenient = ParseOptions {
+        allow_sign_only_numbers: true,
+        ..ParseOptions::default()
+    };
+    assert_eq!(
+        run_example_with("examples/hworld2.ws", "", &lenient),
+        "Hello, world of spaces!\r\n"
+    );
+    assert_eq!(
+        run_example("examples/name.ws", "Ada\n"),
+        "Please enter your name: Hello Ada\n\r\n"
+    );
+    assert_eq!(
+        run_example("examples/fact.ws", "5\n"),
+        "Enter a number: 5! = 120\r\n"
+    );
+    assert_eq!(
+        run_example("examples/loctest.ws", "3\n4\n"),
+        "Enter a number: Enter a number: 7"
+    );
+    assert_eq!(
+        run_example("examples/hanoi.ws", "2\n"),
+        "Enter a number: 1 -> 2\r\n1 -> 3\r\n2 -> 3\r\n"
+    );
+    assert_eq!(
+        run_example("examples/calc.ws", "5\n3\n-1\n"),
+        "Enter some numbers, then -1 to finish\r\nNumber:Number:Number:Total is 8\r\n"
+    );
+}
+
+// Runs a program one instruction at a time, without the bytecode of run_program. Like
+// run_program, there is nothing to do for an empty program.
+fn step_through<R: std::io::BufRead>(
+    vm: &mut VirtualMachine<R, Vec<u8>>,
+) -> Result<(), RuntimeError> {
+    if vm.instructions().is_empty() {
+        return Ok(());
+    }
+    while vm.instructions().get(vm.ip) != Some(&Instruction::EndOfProgram) {
+        vm.run_instruction()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn bytecode_runs_like_instructions_test() {
+    for (name, inputs) in common::EXAMPLES.iter() {
+        let instructions = common::example(name);
+        for input in inputs.iter() {
+            let mut fast = VirtualMachine::with_io(&instructions, input.as_bytes(), Vec::new());
+            let mut slow = VirtualMachine::with_io(&instructions, input.as_bytes(), Vec::new());
+            assert_eq!(step_through(&mut slow), fast.run_program(), "{} {:?}", name, input);
+            assert_eq!(slow.output, fast.output, "{} {:?}", name, input);
+            assert_eq!(slow.stack, fast.stack, "{} {:?}", name, input);
+            assert_eq!(slow.heap, fast.heap, "{} {:?}", name, input);
+        }
+    }
+
+    let mut vm = VirtualMachine::with_io(
+        &[Instruction::Push(0), Instruction::JZero("nowhere".to_string())],
+        &b""[..],
+        Vec::new(),
+    );
+    assert_eq!(
+        Err(RuntimeError {
+            ip: 1,
+            instruction: Some(Instruction::JZero("nowhere".to_string())),
+            kind: RuntimeErrorKind::UnknownLabel("nowhere".to_string()),
+        }),
+        vm.run_program()
+    );
+}
+
+#[test]
+fn fact_prints_large_factorials_with_bignums_test() {
+    let contents = std::fs::read_to_string("examples/fact.ws").unwrap();
+    let instructions = whitespace::parser::parse_program(&contents).unwrap();
+
+    let mut vm = VirtualMachine::<_, _, BigNum>::load(&instructions, "25\n".as_bytes(), Vec::new());
+    assert_eq!(Ok(()), vm.run_program());
+    assert_eq!(
+        "Enter a number: 25! = 15511210043330985984000000\r\n",
+        String::from_utf8(vm.output).unwrap()
+    );
+
+    let mut vm = VirtualMachine::with_io(&instructions, "25\n".as_bytes(), Vec::new());
+    assert_eq!(
+        RuntimeErrorKind::ArithmeticOverflow,
+        vm.run_program().unwrap_err().kind
+    );
+}
+
+#[test]
+fn instruction_pushbig_test() {
+    use whitespace::arithmetic::IntegerMode;
+
+    let big: BigNum = "-123456789012345678901234567890".parse().unwrap();
+    let instructions = [
+        Instruction::PushBig(big.clone()),
+        Instruction::Duplicate,
+        Instruction::Mul,
+        Instruction::PrintInt,
+    ];
+    let mut vm = VirtualMachine::<_, _, BigNum>::load(&instructions, std::io::empty(), Vec::new());
+    let _ = vm.run_n(4);
+    assert_eq!(
+        "15241578753238836750495351562536198787501905199875019052100",
+        String::from_utf8(vm.output).unwrap()
+    );
+
+    let mut vm = VirtualMachine::new(&[Instruction::PushBig(big.clone())]);
+    assert_eq!(
+        RuntimeErrorKind::ArithmeticOverflow,
+        vm.run_instruction().unwrap_err().kind
+    );
+    let mut vm = VirtualMachine::new(&[Instruction::PushBig(big)]);
+    vm.integer_mode = IntegerMode::Saturating;
+    let _ = vm.run_instruction();
+    assert_eq!(vec![i32::MIN], vm.stack);
+}
+
+#[test]
+fn bignum_heap_test() {
+    let mut vm = VirtualMachine::<_, _, BigNum>::load(
+        &[
+            Instruction::Push(3),
+            Instruction::PushBig("99999999999999999999".parse().unwrap()),
+            Instruction::Store,
+            Instruction::Push(3),
+            Instruction::Retrieve,
+        ],
+        std::io::empty(),
+        Vec::new(),
+    );
+    let _ = vm.run_n(5);
+    assert_eq!(BigNum::from(0), vm.heap[2]);
+    assert_eq!(
+        vec!["99999999999999999999".parse::<BigNum>().unwrap()],
+        vm.stack
+    );
+
+    let mut vm = VirtualMachine::<_, _, BigNum>::load(
+        &[
+            Instruction::PushBig("99999999999999999999".parse().unwrap()),
+            Instruction::Retrieve,
+        ],
+        std::io::empty(),
+        Vec::new(),
+    );
+    assert_eq!(
+        RuntimeErrorKind::InvalidHeapAddress(i64::MAX),
+        vm.run_n(2).unwrap_err().kind
+    );
+}
+
+#[test]
+fn runtime_error_carries_its_context_test() {
+    let mut vm = VirtualMachine::new(&[Instruction::Push(1), Instruction::Add]);
+    let error = vm.run_n(2).unwrap_err();
+
+    assert_eq!(error.ip, 1);
+    assert_eq!(error.instruction, Some(Instruction::Add));
+    assert_eq!(
+        error.kind,
+        RuntimeErrorKind::StackUnderflow {
+            needed: 2,
+            available: 1
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "not enough elements on the stack: 2 needed, 1 available (instruction 1: Add)"
+    );
+}
+
+#[test]
+fn runtime_error_kinds_test() {
+    let mut vm = VirtualMachine::new(&[Instruction::Jump("nowhere".to_string())]);
+    assert_eq!(
+        vm.run_instruction().unwrap_err().kind,
+        RuntimeErrorKind::UnknownLabel("nowhere".to_string())
+    );
+
+    let mut vm = VirtualMachine::new(&[Instruction::EndOfSubroutine]);
+    assert_eq!(
+        vm.run_instruction().unwrap_err().kind,
+        RuntimeErrorKind::CallStackUnderflow
+    );
+
+    let mut vm = VirtualMachine::new(&[Instruction::Push(-3), Instruction::Retrieve]);
+    assert_eq!(
+        vm.run_n(2).unwrap_err().kind,
+        RuntimeErrorKind::InvalidHeapAddress(-3)
+    );
+
+    let mut vm = VirtualMachine::new(&[Instruction::Push(1), Instruction::Slide(-2)]);
+    assert_eq!(
+        vm.run_n(2).unwrap_err().kind,
+        RuntimeErrorKind::NegativeArgument(-2)
+    );
+}
+
+#[test]
+fn run_n_stops_at_the_first_error_test() {
+    let mut vm = VirtualMachine::new(&[Instruction::Add, Instruction::Push(1)]);
+    assert_eq!(Ok(()), vm.run_n(0));
+    assert_eq!(0, vm.ip);
+
+    let error = vm.run_n(2).unwrap_err();
+    assert_eq!(0, error.ip);
+    assert!(vm.stack.is_empty());
+}
+
+#[test]
+fn running_past_the_last_instruction_is_an_error_test() {
+    let mut vm = VirtualMachine::new(&[Instruction::Push(1)]);
+    let error = vm.run_program().unwrap_err();
+
+    assert_eq!(error.ip, 1);
+    assert_eq!(error.instruction, None);
+    assert_eq!(error.kind, RuntimeErrorKind::InstructionPointerOutOfBounds);
+
+    let mut vm = VirtualMachine::new(&[Instruction::Jump("a".to_string())]);
+    vm.ip = 5;
+    let error = vm.run_instruction().unwrap_err();
+    assert_eq!(error.kind, RuntimeErrorKind::InstructionPointerOutOfBounds);
+}
+
+fn run_binary_operation(
+    operation: Instruction,
+    left: i32,
+    right: i32,
+) -> Result<Vec<i32>, RuntimeErrorKind> {
+    let mut vm =
+        VirtualMachine::new(&[Instruction::Push(left), Instruction::Push(right), operation]);
+    vm.run_n(3).map(|_| vm.stack.clone()).map_err(|e| e.kind)
+}
+
+#[test]
+fn instruction_div_rounds_towards_negative_infinity_test() {
+    assert_eq!(run_binary_operation(Instruction::Div, 7, 2), Ok(vec![3]));
+    assert_eq!(run_binary_operation(Instruction::Div, -7, 2), Ok(vec![-4]));
+    assert_eq!(run_binary_operation(Instruction::Div, 7, -2), Ok(vec![-4]));
+    assert_eq!(run_binary_operation(Instruction::Div, -7, -2), Ok(vec![3]));
+    assert_eq!(run_binary_operation(Instruction::Div, -6, 2), Ok(vec![-3]));
+    assert_eq!(run_binary_operation(Instruction::Div, 0, -2), Ok(vec![0]));
+}
+
+#[test]
+fn instruction_mod_follows_the_sign_of_the_divisor_test() {
+    assert_eq!(run_binary_operation(Instruction::Mod, 7, 2), Ok(vec![1]));
+    assert_eq!(run_binary_operation(Instruction::Mod, -7, 2), Ok(vec![1]));
+    assert_eq!(run_binary_operation(Instruction::Mod, 7, -2), Ok(vec![-1]));
+    assert_eq!(run_binary_operation(Instruction::Mod, -7, -2), Ok(vec![-1]));
+    assert_eq!(run_binary_operation(Instruction::Mod, -6, 2), Ok(vec![0]));
+    assert_eq!(
+        run_binary_operation(Instruction::Mod, i32::MIN, -1),
+        Ok(vec![0])
+    );
+}
+
+#[test]
+fn instruction_div_mod_by_zero_test() {
+    assert_eq!(
+        run_binary_operation(Instruction::Div, 7, 0),
+        Err(RuntimeErrorKind::DivisionByZero)
+    );
+    assert_eq!(
+        run_binary_operation(Instruction::Mod, 7, 0),
+        Err(RuntimeErrorKind::DivisionByZero)
+    );
+
+    let mut vm =
+        VirtualMachine::new(&[Instruction::Push(7), Instruction::Push(0), Instruction::Div]);
+    assert!(vm.run_n(3).is_err());
+    assert_eq!(vm.stack, vec![7, 0]);
+    assert_eq!(vm.ip, 2);
+}
+
+#[test]
+fn instruction_div_overflow_test() {
+    assert_eq!(
+        run_binary_operation(Instruction::Div, i32::MIN, -1),
+        Err(RuntimeErrorKind::ArithmeticOverflow)
+    );
+}
+
+#[test]
+fn integer_mode_applies_to_arithmetic_test() {
+    use whitespace::arithmetic::IntegerMode;
+
+    let instructions = [
+        Instruction::Push(i32::MAX),
+        Instruction::Push(2),
+        Instruction::Mul,
+    ];
+
+    let mut vm = VirtualMachine::new(&instructions);
+    assert_eq!(
+        vm.run_n(3).unwrap_err().kind,
+        RuntimeErrorKind::ArithmeticOverflow
+    );
+    assert_eq!(vm.stack, vec![i32::MAX, 2]);
+
+    let mut vm = VirtualMachine::new(&instructions);
+    vm.integer_mode = IntegerMode::Wrapping;
+    assert!(vm.run_n(3).is_ok());
+    assert_eq!(vm.stack, vec![-2]);
+
+    let mut vm = VirtualMachine::new(&instructions);
+    vm.integer_mode = IntegerMode::Saturating;
+    assert!(vm.run_n(3).is_ok());
+    assert_eq!(vm.stack, vec![i32::MAX]);
+
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(i32::MIN),
+        Instruction::Push(1),
+        Instruction::Sub,
+    ]);
+    vm.integer_mode = IntegerMode::Saturating;
+    assert!(vm.run_n(3).is_ok());
+    assert_eq!(vm.stack, vec![i32::MIN]);
+}
+
+#[test]
+fn integer_mode_promotes_to_bignums_test() {
+    use whitespace::arithmetic::{IntegerMode, Promoted};
+    use whitespace::parser::parse_program_with;
+
+    let contents = std::fs::read_to_string("examples/fact.ws").unwrap();
+    let options = ParseOptions {
+        integer_mode: IntegerMode::Promote,
+        ..ParseOptions::default()
+    };
+    let instructions = parse_program_with(&contents, &options).unwrap();
+    let mut vm =
+        VirtualMachine::<_, _, Promoted>::load(&instructions, "25\n".as_bytes(), Vec::new());
+    vm.integer_mode = IntegerMode::Promote;
+    assert_eq!(Ok(()), vm.run_program());
+    assert_eq!(
+        "Enter a number: 25! = 15511210043330985984000000\r\n",
+        String::from_utf8(vm.output).unwrap()
+    );
+
+    // literals that do not fit are kept as they are
+    let instructions = parse_program_with("   \t                                \n", &options);
+    assert_eq!(
+        Ok(vec![Instruction::PushBig(BigNum::from(1i64 << 32))]),
+        instructions
+    );
+}
+
+#[test]
+fn integer_mode_promote_needs_values_that_grow_test() {
+    use whitespace::arithmetic::IntegerMode;
+
+    let mut vm = VirtualMachine::new(&[Instruction::Push(1), Instruction::EndOfProgram]);
+    vm.integer_mode = IntegerMode::Promote;
+    let error = vm.run_program().unwrap_err();
+    assert_eq!(RuntimeErrorKind::CanNotPromote, error.kind);
+    assert_eq!(0, error.ip);
+    assert!(vm.stack.is_empty());
+}
+
+#[test]
+fn huge_heap_addresses_test() {
+    let mut vm = VirtualMachine::new(&[
+        Instruction::Push(2_000_000_000),
+        Instruction::Push(7),
+        Instruction::Store,
+        Instruction::Push(2_000_000_000),
+        Instruction::Retrieve,
+        Instruction::Push(1_999_999_999),
+        Instruction::Retrieve,
+    ]);
+    assert!(vm.run_n(7).is_ok());
+    assert_eq!(vm.stack, vec![7, 0]);
+    assert_eq!(2_000_000_001, vm.heap.len());
+
+    let address: BigNum = "4611686018427387904".parse().unwrap();
+    let mut vm = VirtualMachine::<_, _, BigNum>::load(
+        &[
+            Instruction::PushBig(address.clone()),
+            Instruction::Push(7),
+            Instruction::Store,
+            Instruction::PushBig(address),
+            Instruction::Retrieve,
+        ],
+        std::io::empty(),
+        Vec::new(),
+    );
+    assert!(vm.run_n(5).is_ok());
+    assert_eq!(vec![BigNum::from(7)], vm.stack);
 }